@@ -0,0 +1,56 @@
+/// A two-dimensional bitset. Each row is stored as `stride` consecutive `u64` words,
+/// so a grid whose width fits in a single word keeps the layout of a plain `Vec<u64>`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BitGrid {
+    width: usize,
+    stride: usize,
+    words: Vec<u64>,
+}
+
+impl BitGrid {
+    pub fn new(height: usize, width: usize) -> Self {
+        let stride = width.div_ceil(64);
+        Self {
+            width,
+            stride,
+            words: vec![0; height * stride],
+        }
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    #[inline]
+    pub fn get(&self, i: usize, j: usize) -> bool {
+        debug_assert!(j < self.width);
+        (self.words[i * self.stride + j / 64] & 1 << (j % 64)) != 0
+    }
+    #[inline]
+    pub fn set(&mut self, i: usize, j: usize) {
+        debug_assert!(j < self.width);
+        self.words[i * self.stride + j / 64] |= 1 << (j % 64);
+    }
+    #[inline]
+    pub fn reset(&mut self, i: usize, j: usize) {
+        debug_assert!(j < self.width);
+        self.words[i * self.stride + j / 64] &= !(1 << (j % 64));
+    }
+    pub fn row(&self, i: usize) -> &[u64] {
+        &self.words[i * self.stride..(i + 1) * self.stride]
+    }
+    /// The mask of valid bits in the `k`-th word of a row.
+    pub fn word_mask(&self, k: usize) -> u64 {
+        let rest = self.width - 64 * k;
+        if rest >= 64 {
+            !0
+        } else {
+            (1 << rest) - 1
+        }
+    }
+    /// Checks whether every bit of row `i` is set in `self` or `other`.
+    pub fn row_covered_with(&self, other: &Self, i: usize) -> bool {
+        debug_assert_eq!(self.width, other.width);
+        let a = self.row(i);
+        let b = other.row(i);
+        (0..self.stride).all(|k| (a[k] | b[k]) == self.word_mask(k))
+    }
+}
@@ -1,59 +1,623 @@
-use crate::square::Square;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::{bitgrid::BitGrid, square::Square};
+
+mod branching;
+mod cdcl;
+mod cnf;
+mod explain;
+mod grade;
+mod hint;
+mod loops;
+mod parallel;
+mod patterns;
+mod propagate;
+mod text;
+
+pub use self::branching::Branching;
+pub use self::cnf::Cnf;
+pub use self::explain::{Reason, Rule, Step};
+pub use self::grade::{Grade, Technique};
+pub use self::hint::Hint;
+pub use self::patterns::Pattern;
+pub use self::text::{parse_clues, TextError};
 
 #[derive(Debug, Clone, Default)]
 pub struct Stat {
     pub(crate) num_call: u64,
+    // The number of nodes that branched on an edge.
+    pub(crate) num_branch: u64,
+    // The number of nodes that turned out to be contradictory.
+    pub(crate) num_backtrack: u64,
+    // The largest number of branching decisions above a node.
+    pub(crate) max_depth: u64,
+    // The number of times propagation ran.
+    pub(crate) num_propagate: u64,
+    // The number of deductions by each rule, indexed by Rule.
+    pub(crate) deductions: [u64; Rule::ALL.len()],
+    // The number of dead nodes by the kind of contradiction, indexed by ContradictionKind.
+    pub(crate) contradictions: [u64; ContradictionKind::ALL.len()],
+    // The number of times each pattern decided some edges, indexed by Pattern::index.
+    pub(crate) pattern_hits: [u64; Pattern::ALL.len()],
+    pub(crate) propagate_time: Duration,
+    pub(crate) check_time: Duration,
+}
+
+impl Stat {
+    pub fn num_call(&self) -> u64 {
+        self.num_call
+    }
+    pub fn num_branch(&self) -> u64 {
+        self.num_branch
+    }
+    pub fn num_backtrack(&self) -> u64 {
+        self.num_backtrack
+    }
+    pub fn max_depth(&self) -> u64 {
+        self.max_depth
+    }
+    pub fn num_propagate(&self) -> u64 {
+        self.num_propagate
+    }
+    pub fn deductions(&self, rule: Rule) -> u64 {
+        self.deductions[rule as usize]
+    }
+    pub fn contradictions(&self, kind: ContradictionKind) -> u64 {
+        self.contradictions[kind as usize]
+    }
+    pub fn pattern_hits(&self, pattern: Pattern) -> u64 {
+        self.pattern_hits[pattern.index()]
+    }
+    /// Time spent in propagation.
+    pub fn propagate_time(&self) -> Duration {
+        self.propagate_time
+    }
+    /// Time spent checking that the lines can still form a single loop.
+    pub fn check_time(&self) -> Duration {
+        self.check_time
+    }
+}
+
+impl Stat {
+    /// Adds the counts of another search, e.g. from another thread.
+    pub fn merge(&mut self, other: &Stat) {
+        self.num_call += other.num_call;
+        self.num_branch += other.num_branch;
+        self.num_backtrack += other.num_backtrack;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.num_propagate += other.num_propagate;
+        for (count, other) in self.deductions.iter_mut().zip(&other.deductions) {
+            *count += other;
+        }
+        for (count, other) in self.contradictions.iter_mut().zip(&other.contradictions) {
+            *count += other;
+        }
+        for (count, other) in self.pattern_hits.iter_mut().zip(&other.pattern_hits) {
+            *count += other;
+        }
+        self.propagate_time += other.propagate_time;
+        self.check_time += other.check_time;
+    }
+}
+
+impl core::fmt::Display for Stat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "calls: {}, branches: {}, backtracks: {}, max depth: {}, propagations: {}",
+            self.num_call, self.num_branch, self.num_backtrack, self.max_depth, self.num_propagate,
+        )?;
+        f.write_str("deductions:")?;
+        for rule in Rule::ALL {
+            write!(f, " {:?}={}", rule, self.deductions(rule))?;
+        }
+        f.write_str("\ncontradictions:")?;
+        for kind in ContradictionKind::ALL {
+            write!(f, " {:?}={}", kind, self.contradictions(kind))?;
+        }
+        f.write_str("\npattern hits:")?;
+        for pattern in Pattern::ALL {
+            write!(f, " {:?}={}", pattern, self.pattern_hits(pattern))?;
+        }
+        write!(
+            f,
+            "\ntime: propagation {:.3}ms, checks {:.3}ms",
+            self.propagate_time.as_secs_f64() * 1e3,
+            self.check_time.as_secs_f64() * 1e3,
+        )
+    }
+}
+
+/// The reason why a board can't be completed into a single loop.
+/// Vertices are indexed by (row, column) in `0..=n` x `0..=m`, cells in `0..n` x `0..m`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Contradiction {
+    /// The vertex (i, j) has `black` black edges and at most `black_limit`,
+    /// so its degree can be neither 0 nor 2.
+    Vertex {
+        i: usize,
+        j: usize,
+        black: u32,
+        black_limit: u32,
+    },
+    /// The clue `number` in the cell (i, j) is outside `black..=black_limit`.
+    Face {
+        i: usize,
+        j: usize,
+        number: u8,
+        black: u32,
+        black_limit: u32,
+    },
+    /// Black edges touching the vertices `first` and `second` can't be joined into one loop.
+    Global {
+        first: (usize, usize),
+        second: (usize, usize),
+    },
+    /// `pattern` anchored at the cell (i, j) requires an edge that is already decided otherwise.
+    Pattern {
+        pattern: Pattern,
+        i: usize,
+        j: usize,
+    },
+}
+
+impl core::fmt::Display for Contradiction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Contradiction::Vertex {
+                i,
+                j,
+                black,
+                black_limit,
+            } => write!(
+                f,
+                "vertex ({}, {}) has {} line(s) and at most {}, so its degree can't be 0 or 2",
+                i, j, black, black_limit,
+            ),
+            Contradiction::Face {
+                i,
+                j,
+                number,
+                black,
+                black_limit,
+            } => write!(
+                f,
+                "cell ({}, {}) is a {} but has between {} and {} lines",
+                i, j, number, black, black_limit,
+            ),
+            Contradiction::Global { first, second } => write!(
+                f,
+                "lines at vertices {:?} and {:?} can't be part of the same loop",
+                first, second,
+            ),
+            Contradiction::Pattern { pattern, i, j } => write!(
+                f,
+                "pattern {:?} at cell ({}, {}) conflicts with decided edges",
+                pattern, i, j,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Contradiction {}
+
+/// The variant of a [`Contradiction`], for counting them in [`Stat`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContradictionKind {
+    Vertex,
+    Face,
+    Global,
+    Pattern,
+}
+
+impl ContradictionKind {
+    pub const ALL: [ContradictionKind; 4] = [
+        ContradictionKind::Vertex,
+        ContradictionKind::Face,
+        ContradictionKind::Global,
+        ContradictionKind::Pattern,
+    ];
+}
+
+impl Contradiction {
+    pub fn kind(&self) -> ContradictionKind {
+        match self {
+            Contradiction::Vertex { .. } => ContradictionKind::Vertex,
+            Contradiction::Face { .. } => ContradictionKind::Face,
+            Contradiction::Global { .. } => ContradictionKind::Global,
+            Contradiction::Pattern { .. } => ContradictionKind::Pattern,
+        }
+    }
+}
+
+/// The number of solutions found by [`Board::count_solutions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolutionCount {
+    /// The search space was exhausted.
+    Exactly(u64),
+    /// The search stopped early, because enough solutions were found or the limit was reached.
+    AtLeast(u64),
+}
+
+/// The result of [`Board::search`] or [`Board::search_unique`], with the statistics of the search.
+pub enum SearchResult<'sq> {
+    /// A solution was found. For [`Board::search_unique`], it is the only one.
+    Solved(Box<Board<'sq>>, Stat),
+    /// The search space was exhausted: there is no solution.
+    NoSolution(Stat),
+    /// Two different solutions were found. Only returned by [`Board::search_unique`].
+    MultipleSolutions(Box<[Board<'sq>; 2]>, Stat),
+    /// The search gave up before reaching a conclusion.
+    GaveUp(GiveUp, Stat),
+}
+
+impl<'sq> SearchResult<'sq> {
+    pub fn stat(&self) -> &Stat {
+        match self {
+            SearchResult::Solved(_, stat)
+            | SearchResult::NoSolution(stat)
+            | SearchResult::MultipleSolutions(_, stat)
+            | SearchResult::GaveUp(_, stat) => stat,
+        }
+    }
+
+    /// The solution, if exactly one was found.
+    pub fn solution(self) -> Option<Board<'sq>> {
+        match self {
+            SearchResult::Solved(board, _) => Some(*board),
+            _ => None,
+        }
+    }
+}
+
+/// Why a search gave up before exhausting the search space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GiveUp {
+    /// The limit on the number of calls was reached.
+    Limit,
+    /// The deadline set by [`Board::set_deadline`] passed.
+    Timeout,
+    /// The flag set by [`Board::set_cancel_flag`] was raised.
+    Cancelled,
+}
+
+impl core::fmt::Display for GiveUp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            GiveUp::Limit => "the call limit was reached",
+            GiveUp::Timeout => "the time limit was reached",
+            GiveUp::Cancelled => "the search was cancelled",
+        })
+    }
+}
+
+impl std::error::Error for GiveUp {}
+
+// Why a search stopped before exhausting the search space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interrupt {
+    Found,
+    GaveUp(GiveUp),
+}
+
+// What to do with a node of the search tree.
+enum Expansion {
+    Solved,
+    Dead,
+    Branch(Edge),
+}
+
+/// An edge of the grid. Vertices are indexed by (row, column) in `0..=n` x `0..=m`.
+/// `Hori(i, j)` joins the vertices (i, j) and (i, j + 1),
+/// and `Vert(i, j)` joins the vertices (i, j) and (i + 1, j).
+///
+/// For an `n` x `m` board, horizontal edges are in `0..=n` x `0..m` and vertical ones in
+/// `0..n` x `0..=m`. The cell (i, j) is surrounded by `Hori(i, j)` above, `Hori(i + 1, j)` below,
+/// `Vert(i, j)` to the left and `Vert(i, j + 1)` to the right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Edge {
+    Hori(usize, usize),
+    Vert(usize, usize),
+}
+
+/// The state of an edge, see [`Board::get`] and [`Board::set`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum EdgeState {
+    #[default]
+    Unknown,
+    Line,
+    Cross,
+}
+
+impl Edge {
+    pub fn endpoints(self) -> [(usize, usize); 2] {
+        match self {
+            Edge::Hori(i, j) => [(i, j), (i, j + 1)],
+            Edge::Vert(i, j) => [(i, j), (i + 1, j)],
+        }
+    }
 }
 
-// Up to 64
 #[derive(Clone)]
 pub struct Board<'sq> {
     init: &'sq [Vec<Square>],
-    black_hori: Vec<u64>,
-    black_vert: Vec<u64>,
-    white_hori: Vec<u64>,
-    white_vert: Vec<u64>,
+    black_hori: BitGrid,
+    black_vert: BitGrid,
+    white_hori: BitGrid,
+    white_vert: BitGrid,
+    // Edges whose neighboring faces and vertices have to be examined again.
+    pending: Vec<Edge>,
+    // Edges decided since the patterns around them were last matched.
+    unscanned: Vec<Edge>,
+    // Path ends to check for premature loops.
+    loose_ends: Vec<usize>,
+    // The corners of the bounding box of the positive clues, if there are any.
+    positive_clues: Option<[(usize, usize); 2]>,
+    branching: Branching,
+    // Enabled patterns, as a bitset indexed by Pattern::index.
+    patterns: u32,
+    // For a vertex at an end of a path of black edges, the vertex at the other end.
+    ends: Vec<usize>,
+    // For a vertex at an end of a path of black edges, the number of edges in the path.
+    path_len: Vec<usize>,
+    num_black: usize,
+    // A vertex on a closed loop of black edges and the length of the loop.
+    closed_loop: Option<(usize, usize)>,
+    // Deductions made so far, if they are being recorded.
+    log: Option<Vec<Step>>,
+    deadline: Option<Instant>,
+    cancel: Option<Arc<AtomicBool>>,
+    // The number of branching decisions above this node of the search.
+    depth: u64,
+    // In a parallel search, the position of the first solution found so far and the position of
+    // the subtree of this node. The node is abandoned once a solution is found before it.
+    abort: Option<(Arc<AtomicUsize>, usize)>,
 }
 
 impl<'sq> Board<'sq> {
     pub fn new(init: &'sq [Vec<Square>]) -> Self {
         let n = init.len();
         let m = init[0].len();
-        assert!(m <= 63);
         assert!(init.iter().all(|v| v.len() == m));
-        Self {
+        let mut board = Self {
             init,
-            black_hori: vec![0; n + 1],
-            black_vert: vec![0; n],
-            white_hori: vec![0; n + 1],
-            white_vert: vec![0; n],
+            black_hori: BitGrid::new(n + 1, m),
+            black_vert: BitGrid::new(n, m + 1),
+            white_hori: BitGrid::new(n + 1, m),
+            white_vert: BitGrid::new(n, m + 1),
+            pending: vec![],
+            unscanned: vec![],
+            loose_ends: vec![],
+            positive_clues: None,
+            branching: Branching::default(),
+            patterns: (1 << Pattern::ALL.len()) - 1,
+            ends: (0..(n + 1) * (m + 1)).collect(),
+            path_len: vec![0; (n + 1) * (m + 1)],
+            num_black: 0,
+            closed_loop: None,
+            log: None,
+            deadline: None,
+            cancel: None,
+            depth: 0,
+            abort: None,
+        };
+        board.schedule_all();
+        let positive = |&(i, j): &(usize, usize)| init[i][j].get_number().unwrap_or(0) > 0;
+        let cells = (0..n).flat_map(|i| (0..m).map(move |j| (i, j)));
+        board.positive_clues = cells.filter(positive).fold(None, |bounds, (i, j)| {
+            let [(top, left), (bottom, right)] = bounds.unwrap_or([(i, j); 2]);
+            Some([(top.min(i), left.min(j)), (bottom.max(i), right.max(j))])
+        });
+        board
+    }
+    /// Sets how the search picks the edge to branch on.
+    pub fn set_branching(&mut self, branching: Branching) {
+        self.branching = branching;
+    }
+    /// Makes searches give up with [`GiveUp::Timeout`] once `deadline` has passed.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+    /// Makes searches give up with [`GiveUp::Cancelled`] once `flag` is set,
+    /// e.g. from another thread.
+    pub fn set_cancel_flag(&mut self, flag: Option<Arc<AtomicBool>>) {
+        self.cancel = flag;
+    }
+    /// The clues of the puzzle.
+    pub fn clues(&self) -> &'sq [Vec<Square>] {
+        self.init
+    }
+    /// All edges, horizontal ones first, in row-major order.
+    pub fn edges(&self) -> impl Iterator<Item = Edge> {
+        let n = self.init.len();
+        let m = self.init[0].len();
+        let hori = (0..n + 1).flat_map(move |i| (0..m).map(move |j| Edge::Hori(i, j)));
+        let vert = (0..n).flat_map(move |i| (0..m + 1).map(move |j| Edge::Vert(i, j)));
+        hori.chain(vert)
+    }
+    /// The state of an edge. Panics if the edge is outside of the board.
+    pub fn get(&self, edge: Edge) -> EdgeState {
+        assert!(self.contains(edge), "{:?} is outside of the board", edge);
+        if self.is_black(edge) {
+            EdgeState::Line
+        } else if self.is_white(edge) {
+            EdgeState::Cross
+        } else {
+            EdgeState::Unknown
+        }
+    }
+    /// Sets the state of an edge. Panics if the edge is outside of the board.
+    /// Deciding an unknown edge is cheap; changing a decided edge rebuilds the paths of lines.
+    /// Either way, the next propagation reexamines the board.
+    pub fn set(&mut self, edge: Edge, state: EdgeState) {
+        assert!(self.contains(edge), "{:?} is outside of the board", edge);
+        let old = self.get(edge);
+        if old == state {
+            return;
         }
+        if old == EdgeState::Unknown {
+            self.assign(edge, state == EdgeState::Line);
+            return;
+        }
+        let (Edge::Hori(i, j) | Edge::Vert(i, j)) = edge;
+        let (black, white) = match edge {
+            Edge::Hori(..) => (&mut self.black_hori, &mut self.white_hori),
+            Edge::Vert(..) => (&mut self.black_vert, &mut self.white_vert),
+        };
+        black.reset(i, j);
+        white.reset(i, j);
+        match state {
+            EdgeState::Line => black.set(i, j),
+            EdgeState::Cross => white.set(i, j),
+            EdgeState::Unknown => {}
+        }
+        self.relink();
+    }
+    /// The four edges around the cell (i, j).
+    pub fn cell_edges(&self, i: usize, j: usize) -> impl Iterator<Item = Edge> {
+        assert!(i < self.init.len() && j < self.init[0].len());
+        Self::face_edges(i, j).into_iter()
+    }
+    fn contains(&self, edge: Edge) -> bool {
+        let n = self.init.len();
+        let m = self.init[0].len();
+        match edge {
+            Edge::Hori(i, j) => i <= n && j < m,
+            Edge::Vert(i, j) => i < n && j <= m,
+        }
+    }
+    // Rebuilds the paths of lines from scratch and schedules the whole board for propagation.
+    fn relink(&mut self) {
+        let n = self.init.len();
+        let m = self.init[0].len();
+        let lines: Vec<_> = self.edges().filter(|&e| self.is_black(e)).collect();
+        self.black_hori = BitGrid::new(n + 1, m);
+        self.black_vert = BitGrid::new(n, m + 1);
+        self.ends = (0..(n + 1) * (m + 1)).collect();
+        self.path_len = vec![0; (n + 1) * (m + 1)];
+        self.num_black = 0;
+        self.closed_loop = None;
+        for edge in lines {
+            match edge {
+                Edge::Hori(i, j) => self.black_hori.set(i, j),
+                Edge::Vert(i, j) => self.black_vert.set(i, j),
+            }
+            self.link(edge);
+        }
+        self.schedule_all();
+    }
+    // Queues every edge and vertex, so that the next propagation looks at the whole board.
+    fn schedule_all(&mut self) {
+        self.pending = self.edges().collect();
+        self.unscanned = self.pending.clone();
+        self.loose_ends = (0..self.ends.len()).collect();
+    }
+    fn is_black(&self, edge: Edge) -> bool {
+        match edge {
+            Edge::Hori(i, j) => self.black_hori.get(i, j),
+            Edge::Vert(i, j) => self.black_vert.get(i, j),
+        }
+    }
+    fn is_white(&self, edge: Edge) -> bool {
+        match edge {
+            Edge::Hori(i, j) => self.white_hori.get(i, j),
+            Edge::Vert(i, j) => self.white_vert.get(i, j),
+        }
+    }
+    fn is_undecided(&self, edge: Edge) -> bool {
+        !self.is_black(edge) && !self.is_white(edge)
+    }
+    // Decides an undecided edge and schedules its neighborhood for propagation.
+    fn assign(&mut self, edge: Edge, black: bool) {
+        debug_assert!(self.is_undecided(edge));
+        let grid = match (edge, black) {
+            (Edge::Hori(..), false) => &mut self.white_hori,
+            (Edge::Hori(..), true) => &mut self.black_hori,
+            (Edge::Vert(..), false) => &mut self.white_vert,
+            (Edge::Vert(..), true) => &mut self.black_vert,
+        };
+        let (Edge::Hori(i, j) | Edge::Vert(i, j)) = edge;
+        grid.set(i, j);
+        if black {
+            self.link(edge);
+        }
+        self.pending.push(edge);
+        self.unscanned.push(edge);
+    }
+    // Assigns an undecided edge, or checks that a decided one agrees.
+    // Fails with the edge if it is already decided the other way.
+    fn decide(&mut self, edge: Edge, black: bool) -> Result<(), Edge> {
+        if self.is_undecided(edge) {
+            self.assign(edge, black);
+        } else if self.is_black(edge) != black {
+            return Err(edge);
+        }
+        Ok(())
+    }
+    // The edges around the face (i, j).
+    fn face_edges(i: usize, j: usize) -> [Edge; 4] {
+        [
+            Edge::Hori(i, j),
+            Edge::Hori(i + 1, j),
+            Edge::Vert(i, j),
+            Edge::Vert(i, j + 1),
+        ]
+    }
+    /// The two to four edges incident to the vertex (i, j).
+    pub fn vertex_edges(&self, i: usize, j: usize) -> impl Iterator<Item = Edge> {
+        let n = self.init.len();
+        let m = self.init[0].len();
+        assert!(i <= n && j <= m);
+        [
+            (i > 0).then(|| Edge::Vert(i - 1, j)),
+            (j > 0).then(|| Edge::Hori(i, j - 1)),
+            (i < n).then_some(Edge::Vert(i, j)),
+            (j < m).then_some(Edge::Hori(i, j)),
+        ]
+        .into_iter()
+        .flatten()
+    }
+    fn vertex_black_degree(&self, i: usize, j: usize) -> usize {
+        self.vertex_edges(i, j)
+            .filter(|&e| self.is_black(e))
+            .count()
+    }
+    // The faces on both sides of an edge.
+    fn edge_faces(&self, edge: Edge) -> impl Iterator<Item = (usize, usize)> {
+        let n = self.init.len();
+        let m = self.init[0].len();
+        let faces = match edge {
+            Edge::Hori(i, j) => [(i > 0).then(|| (i - 1, j)), (i < n).then_some((i, j))],
+            Edge::Vert(i, j) => [(j > 0).then(|| (i, j - 1)), (j < m).then_some((i, j))],
+        };
+        faces.into_iter().flatten()
     }
     pub fn finished(&self) -> bool {
         if self.check_consistency().is_err() {
             return false;
         }
         let n = self.init.len();
-        let m = self.init[0].len();
         for i in 0..n + 1 {
-            if (self.white_hori[i] | self.black_hori[i]) != (1 << m) - 1 {
+            if !self.white_hori.row_covered_with(&self.black_hori, i) {
                 return false;
             }
         }
         for i in 0..n {
-            if (self.white_vert[i] | self.black_vert[i]) != (1 << (m + 1)) - 1 {
+            if !self.white_vert.row_covered_with(&self.black_vert, i) {
                 return false;
             }
         }
         true
     }
-    pub fn check_consistency(&self) -> Result<(), ()> {
+    pub fn check_consistency(&self) -> Result<(), Contradiction> {
         self.check_vertices()?;
         self.check_faces()?;
         self.check_global()?;
         Ok(())
     }
-    pub fn check_vertices(&self) -> Result<(), ()> {
+    pub fn check_vertices(&self) -> Result<(), Contradiction> {
         let n = self.init.len();
         let m = self.init[0].len();
         for i in 0..n + 1 {
@@ -61,60 +625,67 @@ impl<'sq> Board<'sq> {
                 let mut black_limit = 0;
                 let mut black = 0;
                 if i > 0 {
-                    if (self.black_vert[i - 1] & 1 << j) != 0 {
+                    if self.black_vert.get(i - 1, j) {
                         black += 1;
                     }
-                    if (self.white_vert[i - 1] & 1 << j) == 0 {
+                    if !self.white_vert.get(i - 1, j) {
                         black_limit += 1;
                     }
                 }
                 if j > 0 {
-                    if (self.black_hori[i] & 1 << (j - 1)) != 0 {
+                    if self.black_hori.get(i, j - 1) {
                         black += 1;
                     }
-                    if (self.white_hori[i] & 1 << (j - 1)) == 0 {
+                    if !self.white_hori.get(i, j - 1) {
                         black_limit += 1;
                     }
                 }
                 if i < n {
-                    if (self.black_vert[i] & 1 << j) != 0 {
+                    if self.black_vert.get(i, j) {
                         black += 1;
                     }
-                    if (self.white_vert[i] & 1 << j) == 0 {
+                    if !self.white_vert.get(i, j) {
                         black_limit += 1;
                     }
                 }
                 if j < m {
-                    if (self.black_hori[i] & 1 << j) != 0 {
+                    if self.black_hori.get(i, j) {
                         black += 1;
                     }
-                    if (self.white_hori[i] & 1 << j) == 0 {
+                    if !self.white_hori.get(i, j) {
                         black_limit += 1;
                     }
                 }
                 if [0, 2].iter().all(|&x| x < black || x > black_limit) {
-                    return Err(());
+                    return Err(Contradiction::Vertex {
+                        i,
+                        j,
+                        black,
+                        black_limit,
+                    });
                 }
             }
         }
         Ok(())
     }
 
-    pub fn check_faces(&self) -> Result<(), ()> {
+    pub fn check_faces(&self) -> Result<(), Contradiction> {
         let n = self.init.len();
         let m = self.init[0].len();
         for i in 0..n {
             for j in 0..m {
-                let white = (self.white_hori[i] & 1 << j).count_ones()
-                    + (self.white_hori[i + 1] & 1 << j).count_ones()
-                    + (self.white_vert[i] & 3 << j).count_ones();
-                let black = (self.black_hori[i] & 1 << j).count_ones()
-                    + (self.black_hori[i + 1] & 1 << j).count_ones()
-                    + (self.black_vert[i] & 3 << j).count_ones();
+                let white = count_around_face(&self.white_hori, &self.white_vert, i, j);
+                let black = count_around_face(&self.black_hori, &self.black_vert, i, j);
                 let black_limit = 4 - white;
                 if let Some(number) = self.init[i][j].get_number() {
                     if u32::from(number) < black || black_limit < u32::from(number) {
-                        return Err(());
+                        return Err(Contradiction::Face {
+                            i,
+                            j,
+                            number,
+                            black,
+                            black_limit,
+                        });
                     }
                 }
             }
@@ -122,7 +693,7 @@ impl<'sq> Board<'sq> {
         Ok(())
     }
 
-    pub fn check_global(&self) -> Result<(), ()> {
+    pub fn check_global(&self) -> Result<(), Contradiction> {
         let n = self.init.len();
         let m = self.init[0].len();
         let mut uf = crate::uf::UnionFind::new((n + 1) * (m + 1));
@@ -130,10 +701,10 @@ impl<'sq> Board<'sq> {
         for i in 0..n + 1 {
             for j in 0..m {
                 let v = i * (m + 1) + j;
-                if (self.white_hori[i] & 1 << j) == 0 {
+                if !self.white_hori.get(i, j) {
                     uf.unite(v, v + 1);
                 }
-                if (self.black_hori[i] & 1 << j) != 0 {
+                if self.black_hori.get(i, j) {
                     vertices.push(v);
                 }
             }
@@ -141,222 +712,218 @@ impl<'sq> Board<'sq> {
         for i in 0..n {
             for j in 0..m + 1 {
                 let v = i * (m + 1) + j;
-                if (self.white_vert[i] & 1 << j) == 0 {
+                if !self.white_vert.get(i, j) {
                     uf.unite(v, v + m + 1);
                 }
-                if (self.black_vert[i] & 1 << j) != 0 {
+                if self.black_vert.get(i, j) {
                     vertices.push(v);
                 }
             }
         }
-        let mut roots: Vec<_> = vertices.into_iter().map(|v| uf.root(v)).collect();
-        roots.dedup();
-        if roots.len() <= 1 {
-            Ok(())
-        } else {
-            Err(())
+        let as_coord = |v: usize| (v / (m + 1), v % (m + 1));
+        if let Some(&first) = vertices.first() {
+            let root = uf.root(first);
+            for &v in &vertices[1..] {
+                if uf.root(v) != root {
+                    return Err(Contradiction::Global {
+                        first: as_coord(first),
+                        second: as_coord(v),
+                    });
+                }
+            }
         }
+        Ok(())
     }
 
-    pub fn search(&mut self, stat: &mut Stat, limit: u64) -> bool {
-        if stat.num_call >= limit {
-            return false;
-        }
-        stat.num_call += 1;
-        if self.finished() {
-            return true;
-        }
-        if self.check_consistency().is_err() {
-            return false;
+    /// Searches for a solution.
+    pub fn search(&self, limit: u64) -> SearchResult<'sq> {
+        self.search_for(limit, 1)
+    }
+
+    /// Searches for a solution and then for a second one, to tell whether the solution is unique.
+    /// Gives up as soon as the budget runs out, even if a solution was found.
+    pub fn search_unique(&self, limit: u64) -> SearchResult<'sq> {
+        self.search_for(limit, 2)
+    }
+
+    fn search_for(&self, limit: u64, max_solutions: usize) -> SearchResult<'sq> {
+        let mut stat = Stat::default();
+        let mut found = vec![];
+        let result = self.clone().search_all(&mut stat, limit, &mut |board| {
+            found.push(board.clone());
+            found.len() >= max_solutions
+        });
+        if let Err(Interrupt::GaveUp(reason)) = result {
+            return SearchResult::GaveUp(reason, stat);
         }
-        let n = self.init.len();
-        let m = self.init[0].len();
-        let old = self.clone();
-        if self.fill_determined().is_some() {
-            if self.search(stat, limit) {
-                return true;
+        let second = found.pop();
+        match (found.pop(), second) {
+            (Some(first), Some(second)) => {
+                SearchResult::MultipleSolutions(Box::new([first, second]), stat)
             }
-            *self = old;
-            return false;
+            (None, Some(solution)) => SearchResult::Solved(Box::new(solution), stat),
+            _ => SearchResult::NoSolution(stat),
         }
-        // very naive search
-        for i in 0..n + 1 {
-            let white = self.white_hori[i];
-            let black = self.black_hori[i];
-            let occupied = white | black;
-            for j in 0..m {
-                if (occupied & 1 << j) != 0 {
-                    continue;
-                }
-                self.white_hori[i] |= 1 << j;
-                if self.search(stat, limit) {
-                    return true;
-                }
-                self.white_hori[i] ^= 1 << j;
-                if stat.num_call >= limit {
-                    return false;
-                }
-                self.black_hori[i] |= 1 << j;
-                if self.search(stat, limit) {
-                    return true;
-                }
-                self.black_hori[i] ^= 1 << j;
-                return false;
-            }
+    }
+
+    /// Enumerates solutions lazily. Each solution is yielded as a separate solved board.
+    pub fn solutions(&self, limit: u64) -> Solutions<'sq> {
+        Solutions {
+            stack: vec![self.clone()],
+            stat: Stat::default(),
+            limit,
+            gave_up: None,
         }
-        for i in 0..n {
-            let white = self.white_vert[i];
-            let black = self.black_vert[i];
-            let occupied = white | black;
-            for j in 0..m + 1 {
-                if (occupied & 1 << j) != 0 {
-                    continue;
-                }
-                self.white_vert[i] |= 1 << j;
-                if self.search(stat, limit) {
-                    return true;
-                }
-                self.white_vert[i] ^= 1 << j;
-                if stat.num_call >= limit {
-                    return false;
-                }
-                self.black_vert[i] |= 1 << j;
-                if self.search(stat, limit) {
-                    return true;
+    }
+
+    /// Counts solutions, stopping as soon as `max_solutions` of them are found.
+    pub fn count_solutions(&self, limit: u64, max_solutions: u64) -> (SolutionCount, Stat) {
+        let mut stat = Stat::default();
+        let mut count = 0;
+        let result = self.clone().search_all(&mut stat, limit, &mut |_| {
+            count += 1;
+            count >= max_solutions
+        });
+        let count = match result {
+            Ok(()) => SolutionCount::Exactly(count),
+            Err(_) => SolutionCount::AtLeast(count),
+        };
+        (count, stat)
+    }
+
+    // Calls on_solution for every solution until it returns true.
+    fn search_all<F: FnMut(&Self) -> bool>(
+        mut self,
+        stat: &mut Stat,
+        limit: u64,
+        on_solution: &mut F,
+    ) -> Result<(), Interrupt> {
+        self.check_budget(stat, limit).map_err(Interrupt::GaveUp)?;
+        stat.num_call += 1;
+        match self.expand(stat) {
+            Expansion::Solved => {
+                if on_solution(&self) {
+                    Err(Interrupt::Found)
+                } else {
+                    Ok(())
                 }
-                self.black_vert[i] ^= 1 << j;
-                return false;
+            }
+            Expansion::Dead => Ok(()),
+            Expansion::Branch(edge) => {
+                stat.num_branch += 1;
+                self.depth += 1;
+                let mut white = self.clone();
+                white.assign(edge, false);
+                white.search_all(stat, limit, on_solution)?;
+                self.assign(edge, true);
+                self.search_all(stat, limit, on_solution)
             }
         }
-        false
     }
 
-    // Fill segments that are uniquely determined.
-    fn fill_determined(&mut self) -> Option<()> {
-        let n = self.init.len();
-        let m = self.init[0].len();
-        let mut ret = None;
-        // faces
-        for i in 0..n {
-            for j in 0..m {
-                let number = if let Some(number) = self.init[i][j].get_number() {
-                    u32::from(number)
-                } else {
-                    continue;
-                };
-                let white = (self.white_hori[i] & 1 << j).count_ones()
-                    + (self.white_hori[i + 1] & 1 << j).count_ones()
-                    + (self.white_vert[i] & 3 << j).count_ones();
-                let black = (self.black_hori[i] & 1 << j).count_ones()
-                    + (self.black_hori[i + 1] & 1 << j).count_ones()
-                    + (self.black_vert[i] & 3 << j).count_ones();
-                let black_limit = 4 - white;
-                if number == black_limit && number > black {
-                    // fill with black
-                    self.black_hori[i] |= !self.white_hori[i] & 1 << j;
-                    self.black_hori[i + 1] |= !self.white_hori[i + 1] & 1 << j;
-                    self.black_vert[i] |= !self.white_vert[i] & 3 << j;
-                    ret = Some(());
-                }
-                if number == black && number < black_limit {
-                    // fill with white
-                    self.white_hori[i] |= !self.black_hori[i] & 1 << j;
-                    self.white_hori[i + 1] |= !self.black_hori[i + 1] & 1 << j;
-                    self.white_vert[i] |= !self.black_vert[i] & 3 << j;
-                    ret = Some(());
-                }
+    // Whether the search may expand another node.
+    fn check_budget(&self, stat: &Stat, limit: u64) -> Result<(), GiveUp> {
+        if stat.num_call >= limit {
+            return Err(GiveUp::Limit);
+        }
+        if self
+            .cancel
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed))
+        {
+            return Err(GiveUp::Cancelled);
+        }
+        if let Some((first, k)) = &self.abort {
+            if first.load(Ordering::Relaxed) < *k {
+                return Err(GiveUp::Cancelled);
             }
         }
-        // vertices
-        for i in 0..n + 1 {
-            for j in 0..m + 1 {
-                let mut black = 0;
-                let mut black_limit = 0;
-                let mut unfilled = vec![];
-                if i > 0 {
-                    if (self.black_vert[i - 1] & 1 << j) != 0 {
-                        black += 1;
-                    }
-                    if (self.white_vert[i - 1] & 1 << j) == 0 {
-                        black_limit += 1;
-                    }
-                    if ((self.black_vert[i - 1] | self.white_vert[i - 1]) & 1 << j) == 0 {
-                        unfilled.push((i - 1, j, 1));
-                    }
-                }
-                if j > 0 {
-                    if (self.black_hori[i] & 1 << (j - 1)) != 0 {
-                        black += 1;
-                    }
-                    if (self.white_hori[i] & 1 << (j - 1)) == 0 {
-                        black_limit += 1;
-                    }
-                    if ((self.black_hori[i] | self.white_hori[i]) & 1 << (j - 1)) == 0 {
-                        unfilled.push((i, j - 1, 0));
-                    }
-                }
-                if i < n {
-                    if (self.black_vert[i] & 1 << j) != 0 {
-                        black += 1;
-                    }
-                    if (self.white_vert[i] & 1 << j) == 0 {
-                        black_limit += 1;
-                    }
-                    if ((self.black_vert[i] | self.white_vert[i]) & 1 << j) == 0 {
-                        unfilled.push((i, j, 1));
-                    }
-                }
-                if j < m {
-                    if (self.black_hori[i] & 1 << j) != 0 {
-                        black += 1;
-                    }
-                    if (self.white_hori[i] & 1 << j) == 0 {
-                        black_limit += 1;
-                    }
-                    if ((self.black_hori[i] | self.white_hori[i]) & 1 << j) == 0 {
-                        unfilled.push((i, j, 0));
-                    }
-                }
-                // 0?
-                if black == 0 && black_limit == 1 {
-                    for &(x, y, kind) in &unfilled {
-                        if kind == 0 {
-                            self.white_hori[x] |= 1 << y;
-                        } else {
-                            self.white_vert[x] |= 1 << y;
-                        }
-                    }
-                    ret = Some(());
-                }
-                // 2?
-                if black == 1 && black_limit == 2 {
-                    for &(x, y, kind) in &unfilled {
-                        if kind == 0 {
-                            self.black_hori[x] |= 1 << y;
-                        } else {
-                            self.black_vert[x] |= 1 << y;
-                        }
-                    }
-                    ret = Some(());
-                }
-                // 2?
-                if black == 2 && black_limit > 2 {
-                    for &(x, y, kind) in &unfilled {
-                        if kind == 0 {
-                            self.white_hori[x] |= 1 << y;
-                        } else {
-                            self.white_vert[x] |= 1 << y;
-                        }
-                    }
-                    ret = Some(());
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(GiveUp::Timeout);
+        }
+        Ok(())
+    }
+
+    // Propagates pending changes and decides how to continue from this node.
+    fn expand(&mut self, stat: &mut Stat) -> Expansion {
+        stat.max_depth = stat.max_depth.max(self.depth);
+        let start = Instant::now();
+        let propagated = self.propagate(stat);
+        let propagated_at = Instant::now();
+        stat.propagate_time += propagated_at - start;
+        let result = propagated.and_then(|()| self.check_global());
+        stat.check_time += propagated_at.elapsed();
+        if let Err(contradiction) = result {
+            stat.num_backtrack += 1;
+            stat.contradictions[contradiction.kind() as usize] += 1;
+            return Expansion::Dead;
+        }
+        match self.choose_branch() {
+            Some(edge) => Expansion::Branch(edge),
+            None => Expansion::Solved,
+        }
+    }
+}
+
+/// An iterator over the solutions of a board, returned by [`Board::solutions`].
+/// Nodes are visited in the same order as [`Board::search`] visits them.
+pub struct Solutions<'sq> {
+    stack: Vec<Board<'sq>>,
+    stat: Stat,
+    limit: u64,
+    gave_up: Option<GiveUp>,
+}
+
+impl Solutions<'_> {
+    pub fn stat(&self) -> &Stat {
+        &self.stat
+    }
+    /// Why the enumeration was cut short, if it was.
+    pub fn gave_up(&self) -> Option<GiveUp> {
+        self.gave_up
+    }
+}
+
+impl<'sq> Iterator for Solutions<'sq> {
+    type Item = Board<'sq>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let budget = self.stack.last()?.check_budget(&self.stat, self.limit);
+            if let Err(reason) = budget {
+                self.gave_up = Some(reason);
+                return None;
+            }
+            let mut board = self.stack.pop()?;
+            self.stat.num_call += 1;
+            match board.expand(&mut self.stat) {
+                Expansion::Solved => return Some(board),
+                Expansion::Dead => {}
+                Expansion::Branch(edge) => {
+                    self.stat.num_branch += 1;
+                    board.depth += 1;
+                    let mut black = board.clone();
+                    black.assign(edge, true);
+                    board.assign(edge, false);
+                    self.stack.push(black);
+                    self.stack.push(board);
                 }
             }
         }
-
-        ret
     }
 }
 
+// Number of edges around the face (i, j) that are set in the given grids.
+fn count_around_face(hori: &BitGrid, vert: &BitGrid, i: usize, j: usize) -> u32 {
+    u32::from(hori.get(i, j))
+        + u32::from(hori.get(i + 1, j))
+        + u32::from(vert.get(i, j))
+        + u32::from(vert.get(i, j + 1))
+}
+
 impl core::fmt::Display for Board<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let n = self.init.len();
@@ -364,9 +931,9 @@ impl core::fmt::Display for Board<'_> {
         for i in 0..n + 1 {
             f.write_str("+")?;
             for j in 0..m {
-                f.write_str(if (self.white_hori[i] & 1 << j) != 0 {
+                f.write_str(if self.white_hori.get(i, j) {
                     ".+"
-                } else if (self.black_hori[i] & 1 << j) != 0 {
+                } else if self.black_hori.get(i, j) {
                     "-+"
                 } else {
                     " +"
@@ -380,9 +947,9 @@ impl core::fmt::Display for Board<'_> {
                 if j > 0 {
                     f.write_str(self.init[i][j - 1].as_str())?;
                 }
-                f.write_str(if (self.white_vert[i] & 1 << j) != 0 {
+                f.write_str(if self.white_vert.get(i, j) {
                     "."
-                } else if (self.black_vert[i] & 1 << j) != 0 {
+                } else if self.black_vert.get(i, j) {
                     "|"
                 } else {
                     " "
@@ -0,0 +1,65 @@
+use super::{Board, Edge};
+
+/// How the search picks the edge to branch on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Branching {
+    /// The first undecided edge, horizontal edges first, in row-major order.
+    #[default]
+    FirstUndecided,
+    /// An undecided edge next to a path end or a clue with the fewest undecided edges left.
+    MostConstrained,
+    /// An undecided edge next to the highest clue.
+    HighClue,
+    /// An undecided edge that extends the end of a path of black edges.
+    PathEnd,
+}
+
+impl Board<'_> {
+    // Picks an undecided edge according to the branching strategy.
+    // Ties are broken by the order of `edges`.
+    pub(super) fn choose_branch(&self) -> Option<Edge> {
+        let mut undecided = self.edges().filter(|&edge| self.is_undecided(edge));
+        match self.branching {
+            Branching::FirstUndecided => undecided.next(),
+            Branching::MostConstrained => undecided.min_by_key(|&edge| {
+                let at_path_end = edge
+                    .endpoints()
+                    .into_iter()
+                    .filter(|&(i, j)| self.vertex_black_degree(i, j) == 1)
+                    .map(|(i, j)| self.count_undecided(self.vertex_edges(i, j)))
+                    .min()
+                    .unwrap_or(usize::MAX);
+                let at_clue = self
+                    .edge_faces(edge)
+                    .filter(|&(i, j)| self.init[i][j].get_number().is_some())
+                    .map(|(i, j)| self.count_undecided(Self::face_edges(i, j)))
+                    .min()
+                    .unwrap_or(usize::MAX);
+                (at_path_end.min(at_clue), at_path_end.max(at_clue))
+            }),
+            Branching::HighClue => {
+                // max_by_key picks the last maximum, so the order is reversed.
+                let edges: Vec<_> = undecided.collect();
+                edges.into_iter().rev().max_by_key(|&edge| {
+                    self.edge_faces(edge)
+                        .filter_map(|(i, j)| self.init[i][j].get_number())
+                        .max()
+                })
+            }
+            Branching::PathEnd => {
+                let mut undecided = undecided.peekable();
+                let first = *undecided.peek()?;
+                let path_end = undecided.find(|&edge| {
+                    edge.endpoints()
+                        .into_iter()
+                        .any(|(i, j)| self.vertex_black_degree(i, j) == 1)
+                });
+                Some(path_end.unwrap_or(first))
+            }
+        }
+    }
+
+    fn count_undecided(&self, edges: impl IntoIterator<Item = Edge>) -> usize {
+        edges.into_iter().filter(|&e| self.is_undecided(e)).count()
+    }
+}
@@ -0,0 +1,505 @@
+use std::cmp::Reverse;
+
+use super::{Board, Cnf, Contradiction, Edge, Reason, SearchResult, Stat};
+
+// A literal: variable * 2, plus 1 if negated. Variables are numbered from 0 here.
+type Lit = u32;
+
+fn lit_of(dimacs: i32) -> Lit {
+    (dimacs.unsigned_abs() - 1) * 2 + u32::from(dimacs < 0)
+}
+
+fn var(lit: Lit) -> usize {
+    (lit / 2) as usize
+}
+
+// A conflict-driven clause learning solver over the clauses of a `Cnf`.
+struct Solver {
+    clauses: Vec<Vec<Lit>>,
+    // For each literal, the clauses watching it. The watched literals of a clause are its first two.
+    watches: Vec<Vec<usize>>,
+    value: Vec<Option<bool>>,
+    level: Vec<u32>,
+    // The clause that implied each variable, or None for decisions.
+    reason: Vec<Option<usize>>,
+    trail: Vec<Lit>,
+    // Where each decision level starts in the trail.
+    trail_lim: Vec<usize>,
+    // How much of the trail has been propagated.
+    head: usize,
+    activity: Vec<f64>,
+    bump: f64,
+    // The last value of each variable, reused when it is decided again.
+    phase: Vec<bool>,
+}
+
+// The outcome of adding clauses at the root.
+struct Unsat;
+
+impl Solver {
+    fn new(num_vars: usize) -> Self {
+        Solver {
+            clauses: vec![],
+            watches: vec![vec![]; 2 * num_vars],
+            value: vec![None; num_vars],
+            level: vec![0; num_vars],
+            reason: vec![None; num_vars],
+            trail: vec![],
+            trail_lim: vec![],
+            head: 0,
+            activity: vec![0.0; num_vars],
+            bump: 1.0,
+            phase: vec![false; num_vars],
+        }
+    }
+
+    fn lit_value(&self, lit: Lit) -> Option<bool> {
+        self.value[var(lit)].map(|value| value != (lit & 1 != 0))
+    }
+
+    fn decision_level(&self) -> u32 {
+        self.trail_lim.len() as u32
+    }
+
+    fn enqueue(&mut self, lit: Lit, reason: Option<usize>) {
+        let v = var(lit);
+        self.value[v] = Some(lit & 1 == 0);
+        self.level[v] = self.decision_level();
+        self.reason[v] = reason;
+        self.trail.push(lit);
+    }
+
+    // Adds a clause at decision level 0.
+    fn add_clause(&mut self, mut clause: Vec<Lit>) -> Result<(), Unsat> {
+        debug_assert_eq!(self.decision_level(), 0);
+        if clause.iter().any(|&lit| self.lit_value(lit) == Some(true)) {
+            return Ok(());
+        }
+        clause.retain(|&lit| self.lit_value(lit).is_none());
+        match clause.len() {
+            0 => Err(Unsat),
+            1 => {
+                self.enqueue(clause[0], None);
+                Ok(())
+            }
+            _ => {
+                self.attach(clause);
+                Ok(())
+            }
+        }
+    }
+
+    fn attach(&mut self, clause: Vec<Lit>) -> usize {
+        let index = self.clauses.len();
+        self.watches[clause[0] as usize].push(index);
+        self.watches[clause[1] as usize].push(index);
+        self.clauses.push(clause);
+        index
+    }
+
+    // Unit propagation. Returns a conflicting clause, if any.
+    fn propagate(&mut self) -> Option<usize> {
+        while self.head < self.trail.len() {
+            let false_lit = self.trail[self.head] ^ 1;
+            self.head += 1;
+            let mut watching = std::mem::take(&mut self.watches[false_lit as usize]);
+            let mut kept = 0;
+            let mut conflict = None;
+            let mut k = 0;
+            'clauses: while k < watching.len() {
+                let index = watching[k];
+                k += 1;
+                if conflict.is_some() {
+                    watching[kept] = index;
+                    kept += 1;
+                    continue;
+                }
+                // The clause is borrowed, so values are read from the fields directly.
+                let clause = &mut self.clauses[index];
+                if clause[0] == false_lit {
+                    clause.swap(0, 1);
+                }
+                let first = clause[0];
+                if self.value[var(first)].map(|value| value != (first & 1 != 0)) == Some(true) {
+                    watching[kept] = index;
+                    kept += 1;
+                    continue;
+                }
+                for other in 2..clause.len() {
+                    let lit = clause[other];
+                    if self.value[var(lit)].map(|value| value != (lit & 1 != 0)) != Some(false) {
+                        clause.swap(1, other);
+                        self.watches[lit as usize].push(index);
+                        continue 'clauses;
+                    }
+                }
+                watching[kept] = index;
+                kept += 1;
+                if self.lit_value(first) == Some(false) {
+                    conflict = Some(index);
+                } else {
+                    self.enqueue(first, Some(index));
+                }
+            }
+            watching.truncate(kept);
+            self.watches[false_lit as usize] = watching;
+            if conflict.is_some() {
+                return conflict;
+            }
+        }
+        None
+    }
+
+    // Derives the first-UIP clause of a conflict. Its first literal is the one to assert.
+    fn analyze(&mut self, conflict: usize) -> Vec<Lit> {
+        let mut seen = vec![false; self.value.len()];
+        let mut learnt = vec![0];
+        let mut pending = 0;
+        let mut index = self.trail.len();
+        let mut clause = conflict;
+        let mut skip_first = false;
+        loop {
+            for k in usize::from(skip_first)..self.clauses[clause].len() {
+                let lit = self.clauses[clause][k];
+                let v = var(lit);
+                if seen[v] || self.level[v] == 0 {
+                    continue;
+                }
+                seen[v] = true;
+                self.activity[v] += self.bump;
+                if self.level[v] == self.decision_level() {
+                    pending += 1;
+                } else {
+                    learnt.push(lit);
+                }
+            }
+            loop {
+                index -= 1;
+                if seen[var(self.trail[index])] {
+                    break;
+                }
+            }
+            let lit = self.trail[index];
+            pending -= 1;
+            if pending == 0 {
+                learnt[0] = lit ^ 1;
+                break;
+            }
+            // Implied literals come first in their reason clauses.
+            clause = self.reason[var(lit)].unwrap();
+            skip_first = true;
+        }
+        self.bump *= 1.05;
+        if self.bump > 1e100 {
+            for activity in &mut self.activity {
+                *activity *= 1e-100;
+            }
+            self.bump *= 1e-100;
+        }
+        learnt
+    }
+
+    fn backtrack(&mut self, level: u32) {
+        if self.decision_level() <= level {
+            return;
+        }
+        let start = self.trail_lim[level as usize];
+        for &lit in &self.trail[start..] {
+            let v = var(lit);
+            self.phase[v] = lit & 1 == 0;
+            self.value[v] = None;
+            self.reason[v] = None;
+        }
+        self.trail.truncate(start);
+        self.trail_lim.truncate(level as usize);
+        self.head = start;
+    }
+
+    // Looks for a closed loop among the lines so far with another line outside of it,
+    // and adds a cut against it as a conflicting clause. The cut is false at its highest
+    // level, so the solver backtracks to that level first.
+    fn cut_loop(&mut self, cnf: &Cnf) -> Option<usize> {
+        let lines: Vec<_> = self
+            .value
+            .iter()
+            .map(|&value| value == Some(true))
+            .collect();
+        let cut = cnf.loop_cuts(&lines).into_iter().next()?;
+        let mut cut: Vec<_> = cut.into_iter().map(lit_of).collect();
+        cut.sort_by_key(|&lit| Reverse(self.level[var(lit)]));
+        self.backtrack(self.level[var(cut[0])]);
+        Some(self.attach(cut))
+    }
+
+    // Adds a clause found outside of unit propagation whose literals are false except maybe
+    // for the first. If the first is unassigned, it is implied at the current level; otherwise
+    // the clause is a conflict, and the solver backtracks to its highest level like cut_loop.
+    // Returns the conflicting clause, if any.
+    fn learn(&mut self, mut clause: Vec<Lit>) -> Result<Option<usize>, Unsat> {
+        if clause.iter().any(|&lit| self.lit_value(lit) == Some(true)) {
+            return Ok(None);
+        }
+        // Literals that are false at the root stay false.
+        clause.retain(|&lit| self.value[var(lit)].is_none() || self.level[var(lit)] > 0);
+        clause.sort_by_key(|&lit| (self.lit_value(lit).is_some(), Reverse(self.level[var(lit)])));
+        match clause.len() {
+            0 => Err(Unsat),
+            1 => {
+                self.backtrack(0);
+                self.enqueue(clause[0], None);
+                Ok(None)
+            }
+            _ if self.lit_value(clause[0]).is_none() => {
+                let implied = clause[0];
+                let index = self.attach(clause);
+                self.enqueue(implied, Some(index));
+                Ok(None)
+            }
+            _ => {
+                self.backtrack(self.level[var(clause[0])]);
+                Ok(Some(self.attach(clause)))
+            }
+        }
+    }
+
+    fn is_line(&self, cnf: &Cnf, edge: Edge) -> bool {
+        self.value[cnf.var(edge) as usize - 1] == Some(true)
+    }
+
+    // The literals made false by how `edges` are decided so far.
+    fn explain(&self, cnf: &Cnf, edges: impl IntoIterator<Item = Edge>) -> Vec<Lit> {
+        edges
+            .into_iter()
+            .filter_map(|edge| {
+                let var = cnf.var(edge);
+                let line = self.value[var as usize - 1]?;
+                Some(lit_of(if line { -var } else { var }))
+            })
+            .collect()
+    }
+
+    // The lines followed from the vertex `start` until they end or come back to it.
+    fn trace(&self, board: &Board, cnf: &Cnf, start: (usize, usize)) -> Vec<Edge> {
+        let mut lines: Vec<Edge> = vec![];
+        let mut at = start;
+        while let Some(edge) = board
+            .vertex_edges(at.0, at.1)
+            .find(|&e| self.is_line(cnf, e) && lines.last() != Some(&e))
+        {
+            if lines.first() == Some(&edge) {
+                break;
+            }
+            let [u, v] = edge.endpoints();
+            at = if u == at { v } else { u };
+            lines.push(edge);
+        }
+        lines
+    }
+
+    fn decide(&mut self) -> Option<Lit> {
+        let v = (0..self.value.len())
+            .filter(|&v| self.value[v].is_none())
+            .max_by(|&a, &b| {
+                self.activity[a]
+                    .total_cmp(&self.activity[b])
+                    .then(b.cmp(&a))
+            })?;
+        Some(v as Lit * 2 + u32::from(!self.phase[v]))
+    }
+}
+
+impl Board<'_> {
+    // Propagates natively on a copy of this board with the solver's assignment, and turns
+    // what it finds into clauses over the decided edges around each rule: every deduction is
+    // implied by them, and a contradiction is a conflict. Returns the conflicting clause, if any.
+    fn propagate_natively(
+        &self,
+        solver: &mut Solver,
+        cnf: &Cnf,
+        stat: &mut Stat,
+    ) -> Result<Option<usize>, Unsat> {
+        let mut mirror = self.clone();
+        for &lit in &solver.trail {
+            let edge = cnf.edge(var(lit) as i32 + 1);
+            if mirror.is_undecided(edge) {
+                mirror.assign(edge, lit & 1 == 0);
+            }
+        }
+        mirror.log = Some(vec![]);
+        let result = mirror.propagate(stat).and_then(|()| mirror.check_global());
+        let level = solver.decision_level();
+        // The solver takes the steps in order, so it is where the board was before each one.
+        for step in mirror.log.take().unwrap_or_default() {
+            let because = self.explain_step(solver, cnf, step.reason);
+            for (edges, line) in [(&step.lines, true), (&step.crosses, false)] {
+                for &edge in edges {
+                    let var = cnf.var(edge);
+                    let mut clause = vec![lit_of(if line { var } else { -var })];
+                    clause.extend(&because);
+                    if let Some(conflict) = solver.learn(clause)? {
+                        return Ok(Some(conflict));
+                    }
+                    // A fact for the root was learned, so the copy is out of date.
+                    if solver.decision_level() != level {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+        if let Err(contradiction) = result {
+            stat.contradictions[contradiction.kind() as usize] += 1;
+            let clause = self.explain_contradiction(solver, cnf, contradiction);
+            return solver.learn(clause);
+        }
+        Ok(None)
+    }
+
+    fn explain_step(&self, solver: &Solver, cnf: &Cnf, reason: Reason) -> Vec<Lit> {
+        match reason {
+            Reason::Face { i, j, .. } => solver.explain(cnf, Self::face_edges(i, j)),
+            Reason::Vertex { i, j, .. } => solver.explain(cnf, self.vertex_edges(i, j)),
+            Reason::Pattern { i, j, .. } => solver.explain(cnf, self.pattern_edges(i, j)),
+            Reason::PrematureLoop { ends } => {
+                // The path between the ends and any line outside of it.
+                let path = solver.trace(self, cnf, ends[0]);
+                let outside = self
+                    .edges()
+                    .find(|&e| solver.is_line(cnf, e) && !path.contains(&e));
+                solver.explain(cnf, path.into_iter().chain(outside))
+            }
+            Reason::LoopClosed { at } => solver.explain(cnf, solver.trace(self, cnf, at)),
+            // Trials aren't part of propagate, but the whole assignment implies anything.
+            Reason::Trial { .. } => solver.explain(cnf, self.edges()),
+        }
+    }
+
+    fn explain_contradiction(
+        &self,
+        solver: &Solver,
+        cnf: &Cnf,
+        contradiction: Contradiction,
+    ) -> Vec<Lit> {
+        match contradiction {
+            Contradiction::Vertex { i, j, .. } => solver.explain(cnf, self.vertex_edges(i, j)),
+            Contradiction::Face { i, j, .. } => solver.explain(cnf, Self::face_edges(i, j)),
+            Contradiction::Pattern { i, j, .. } => solver.explain(cnf, self.pattern_edges(i, j)),
+            Contradiction::Global { first, second } => {
+                let line_at = |(i, j)| self.vertex_edges(i, j).find(|&e| solver.is_line(cnf, e));
+                let lines = [line_at(first), line_at(second)].into_iter().flatten();
+                // The vertices that first reaches without crossing a cross.
+                let m = self.init[0].len();
+                let index = |(i, j): (usize, usize)| i * (m + 1) + j;
+                let mut reached = vec![false; (self.init.len() + 1) * (m + 1)];
+                let mut stack = vec![first];
+                while let Some((i, j)) = stack.pop() {
+                    if std::mem::replace(&mut reached[index((i, j))], true) {
+                        continue;
+                    }
+                    for edge in self.vertex_edges(i, j) {
+                        if solver.value[cnf.var(edge) as usize - 1] != Some(false) {
+                            stack.extend(edge.endpoints());
+                        }
+                    }
+                }
+                if reached[index(second)] {
+                    // first is on a closed loop, and second is on a line outside of it.
+                    let closed = solver.trace(self, cnf, first);
+                    return solver.explain(cnf, closed.into_iter().chain(lines));
+                }
+                // The crosses around the part of the board that first reaches.
+                let border = self.edges().filter(|&e| {
+                    let [u, v] = e.endpoints();
+                    reached[index(u)] != reached[index(v)]
+                });
+                solver.explain(cnf, border.chain(lines))
+            }
+        }
+    }
+}
+
+impl<'sq> Board<'sq> {
+    /// Searches for a solution with conflict-driven clause learning instead of backtracking.
+    /// After propagating natively, the board is encoded as CNF ([`Board::to_cnf`]); conflicts are
+    /// learned as clauses, the search backjumps over decisions that didn't contribute to them,
+    /// and models with several loops are cut off as they are found.
+    /// Whenever unit propagation stops, the native rules run on the assignment too, and their
+    /// deductions and contradictions are learned as clauses over the edges they looked at.
+    /// Each decision counts as a call.
+    pub fn search_cdcl(&self, limit: u64) -> SearchResult<'sq> {
+        let mut stat = Stat::default();
+        let mut board = self.clone();
+        if let Err(reason) = board.check_budget(&stat, limit) {
+            return SearchResult::GaveUp(reason, stat);
+        }
+        stat.num_call += 1;
+        if board.propagate(&mut stat).is_err() || board.check_global().is_err() {
+            stat.num_backtrack += 1;
+            return SearchResult::NoSolution(stat);
+        }
+        let cnf = board.to_cnf();
+        let mut solver = Solver::new(cnf.num_vars());
+        for clause in &cnf.clauses {
+            if solver
+                .add_clause(clause.iter().map(|&lit| lit_of(lit)).collect())
+                .is_err()
+            {
+                return SearchResult::NoSolution(stat);
+            }
+        }
+        loop {
+            let mut conflict = solver.propagate();
+            if conflict.is_none() {
+                match board.propagate_natively(&mut solver, &cnf, &mut stat) {
+                    Ok(found) => conflict = found,
+                    Err(Unsat) => return SearchResult::NoSolution(stat),
+                }
+                // New literals are unit propagated before going on.
+                if conflict.is_none() && solver.head < solver.trail.len() {
+                    continue;
+                }
+            }
+            if conflict.is_none() {
+                conflict = solver.cut_loop(&cnf);
+            }
+            if let Some(conflict) = conflict {
+                stat.num_backtrack += 1;
+                if solver.decision_level() == 0 {
+                    return SearchResult::NoSolution(stat);
+                }
+                let mut learnt = solver.analyze(conflict);
+                // Backjump to the second highest level in the clause, where it becomes unit.
+                let (k, level) = (1..learnt.len())
+                    .map(|k| (k, solver.level[var(learnt[k])]))
+                    .max_by_key(|&(_, level)| level)
+                    .unwrap_or((0, 0));
+                solver.backtrack(level);
+                if learnt.len() == 1 {
+                    solver.enqueue(learnt[0], None);
+                } else {
+                    learnt.swap(1, k);
+                    let asserting = learnt[0];
+                    let index = solver.attach(learnt);
+                    solver.enqueue(asserting, Some(index));
+                }
+                continue;
+            }
+            if let Some(lit) = solver.decide() {
+                if let Err(reason) = board.check_budget(&stat, limit) {
+                    return SearchResult::GaveUp(reason, stat);
+                }
+                stat.num_call += 1;
+                stat.num_branch += 1;
+                solver.trail_lim.push(solver.trail.len());
+                stat.max_depth = stat.max_depth.max(u64::from(solver.decision_level()));
+                solver.enqueue(lit, None);
+                continue;
+            }
+            // Every variable is assigned and the lines form a single loop.
+            let model: Vec<_> = solver.value.iter().map(|value| value.unwrap()).collect();
+            let mut solution = board;
+            solution.apply_model(&model).unwrap();
+            debug_assert!(solution.finished());
+            return SearchResult::Solved(Box::new(solution), stat);
+        }
+    }
+}
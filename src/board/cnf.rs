@@ -0,0 +1,206 @@
+use super::{Board, Edge};
+use crate::uf::UnionFind;
+
+/// A CNF encoding of a board. Variable `k + 1` is true iff the edge `edges()[k]` is a line,
+/// where edges are numbered like [`Board::edges`].
+///
+/// Clues and vertex degrees are encoded directly. That the lines form a single loop is not:
+/// a model may contain several loops, which [`Cnf::add_loop_cuts`] rules out lazily.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cnf {
+    height: usize,
+    width: usize,
+    /// Clauses of nonzero literals in DIMACS convention.
+    pub clauses: Vec<Vec<i32>>,
+}
+
+impl Cnf {
+    pub fn num_vars(&self) -> usize {
+        (self.height + 1) * self.width + self.height * (self.width + 1)
+    }
+
+    /// The variable of an edge.
+    pub fn var(&self, edge: Edge) -> i32 {
+        let index = match edge {
+            Edge::Hori(i, j) => i * self.width + j,
+            Edge::Vert(i, j) => (self.height + 1) * self.width + i * (self.width + 1) + j,
+        };
+        index as i32 + 1
+    }
+
+    /// The edge of a variable.
+    pub fn edge(&self, var: i32) -> Edge {
+        let index = var as usize - 1;
+        let num_hori = (self.height + 1) * self.width;
+        if index < num_hori {
+            Edge::Hori(index / self.width, index % self.width)
+        } else {
+            let index = index - num_hori;
+            Edge::Vert(index / (self.width + 1), index % (self.width + 1))
+        }
+    }
+
+    /// The encoding in DIMACS format. Comment lines map each variable to its edge.
+    pub fn to_dimacs(&self) -> String {
+        let mut out = format!("c slitherlink {}x{}\n", self.height, self.width);
+        for var in 1..=self.num_vars() as i32 {
+            let (kind, i, j) = match self.edge(var) {
+                Edge::Hori(i, j) => ("hori", i, j),
+                Edge::Vert(i, j) => ("vert", i, j),
+            };
+            out.push_str(&format!("c var {} {} {} {}\n", var, kind, i, j));
+        }
+        out.push_str(&format!(
+            "p cnf {} {}\n",
+            self.num_vars(),
+            self.clauses.len()
+        ));
+        for clause in &self.clauses {
+            for literal in clause {
+                out.push_str(&format!("{} ", literal));
+            }
+            out.push_str("0\n");
+        }
+        out
+    }
+
+    /// Reads the `v` lines of a SAT solver's output into a model indexed by variable - 1.
+    /// Returns None if the output has no `v` lines or misses some variable.
+    pub fn parse_model(&self, output: &str) -> Option<Vec<bool>> {
+        let mut model = vec![None; self.num_vars()];
+        for line in output.lines() {
+            let literals = match line.strip_prefix("v ") {
+                Some(literals) => literals,
+                None => continue,
+            };
+            for literal in literals.split_whitespace() {
+                let literal: i32 = literal.parse().ok()?;
+                if literal == 0 {
+                    continue;
+                }
+                *model.get_mut(literal.unsigned_abs() as usize - 1)? = Some(literal > 0);
+            }
+        }
+        model.into_iter().collect()
+    }
+
+    /// Adds cuts against the loops of a model if it has more than one:
+    /// for each loop L and each line f outside of it, not all of L and f are lines.
+    /// Returns the number of clauses added, so 0 means that the model is a single loop.
+    pub fn add_loop_cuts(&mut self, model: &[bool]) -> usize {
+        let cuts = self.loop_cuts(model);
+        let added = cuts.len();
+        self.clauses.extend(cuts);
+        added
+    }
+
+    // The cuts of add_loop_cuts. `model` may also be a partial assignment with its undecided
+    // edges as false, since a closed loop can't be part of a solution with other lines either way.
+    pub(super) fn loop_cuts(&self, model: &[bool]) -> Vec<Vec<i32>> {
+        let (n, m) = (self.height, self.width);
+        let lines: Vec<_> = (1..=self.num_vars() as i32)
+            .filter(|&var| model[var as usize - 1])
+            .collect();
+        let vertex = |(i, j): (usize, usize)| i * (m + 1) + j;
+        let mut degree = vec![0; (n + 1) * (m + 1)];
+        let mut uf = UnionFind::new((n + 1) * (m + 1));
+        for &var in &lines {
+            let [u, v] = self.edge(var).endpoints().map(vertex);
+            degree[u] += 1;
+            degree[v] += 1;
+            uf.unite(u, v);
+        }
+        let mut components: Vec<(usize, Vec<i32>)> = vec![];
+        for &var in &lines {
+            let root = uf.root(vertex(self.edge(var).endpoints()[0]));
+            match components.iter_mut().find(|(r, _)| *r == root) {
+                Some((_, vars)) => vars.push(var),
+                None => components.push((root, vec![var])),
+            }
+        }
+        let mut cuts = vec![];
+        if components.len() < 2 {
+            return cuts;
+        }
+        for (_, vars) in &components {
+            // Only a closed loop can't be extended into the solution.
+            let closed = vars.iter().all(|&var| {
+                let [u, v] = self.edge(var).endpoints().map(vertex);
+                degree[u] == 2 && degree[v] == 2
+            });
+            if !closed {
+                continue;
+            }
+            for &outside in lines.iter().filter(|var| !vars.contains(var)) {
+                let mut clause: Vec<_> = vars.iter().map(|&var| -var).collect();
+                clause.push(-outside);
+                cuts.push(clause);
+            }
+        }
+        cuts
+    }
+}
+
+// Clauses forbidding every assignment of `vars` whose number of true variables is not allowed.
+fn cardinality(vars: &[i32], allowed: impl Fn(u32) -> bool) -> Vec<Vec<i32>> {
+    (0u32..1 << vars.len())
+        .filter(|bits| !allowed(bits.count_ones()))
+        .map(|bits| {
+            vars.iter()
+                .enumerate()
+                .map(|(k, &var)| if bits >> k & 1 != 0 { -var } else { var })
+                .collect()
+        })
+        .collect()
+}
+
+impl Board<'_> {
+    /// Encodes the clues, the vertex degrees and the decided edges as CNF.
+    pub fn to_cnf(&self) -> Cnf {
+        let n = self.init.len();
+        let m = self.init[0].len();
+        let mut cnf = Cnf {
+            height: n,
+            width: m,
+            clauses: vec![],
+        };
+        for i in 0..n {
+            for j in 0..m {
+                if let Some(number) = self.init[i][j].get_number() {
+                    let vars = Self::face_edges(i, j).map(|e| cnf.var(e));
+                    let clauses = cardinality(&vars, |count| count == u32::from(number));
+                    cnf.clauses.extend(clauses);
+                }
+            }
+        }
+        for i in 0..n + 1 {
+            for j in 0..m + 1 {
+                let vars: Vec<_> = self.vertex_edges(i, j).map(|e| cnf.var(e)).collect();
+                let clauses = cardinality(&vars, |count| count == 0 || count == 2);
+                cnf.clauses.extend(clauses);
+            }
+        }
+        for edge in self.edges() {
+            if self.is_black(edge) {
+                cnf.clauses.push(vec![cnf.var(edge)]);
+            } else if self.is_white(edge) {
+                cnf.clauses.push(vec![-cnf.var(edge)]);
+            }
+        }
+        cnf
+    }
+
+    /// The model of this board's lines, indexed like [`Cnf::parse_model`]. Undecided edges are false.
+    pub fn to_model(&self) -> Vec<bool> {
+        self.edges().map(|e| self.is_black(e)).collect()
+    }
+
+    /// Decides every undecided edge from a model, failing like [`Board::replay`].
+    pub fn apply_model(&mut self, model: &[bool]) -> Result<(), Edge> {
+        let edges: Vec<_> = self.edges().collect();
+        for (edge, &black) in edges.into_iter().zip(model) {
+            self.decide(edge, black)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+use super::{Board, Contradiction, Edge, Pattern, Stat};
+
+/// Why a deduction was made. Counts are taken just before the deduction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// The clue `number` in the cell (i, j) already has `black` lines and `white` crosses.
+    Face {
+        i: usize,
+        j: usize,
+        number: u8,
+        black: u32,
+        white: u32,
+    },
+    /// The vertex (i, j) already has `black` lines and `white` crosses.
+    Vertex {
+        i: usize,
+        j: usize,
+        black: u32,
+        white: u32,
+    },
+    /// The vertices `ends` are both ends of a path, and other lines exist outside of it.
+    PrematureLoop { ends: [(usize, usize); 2] },
+    /// The loop through the vertex `at` is closed.
+    LoopClosed { at: (usize, usize) },
+    /// `pattern` matched, anchored at the cell (i, j).
+    Pattern {
+        pattern: Pattern,
+        i: usize,
+        j: usize,
+    },
+    /// Assuming a line (`black`) or a cross at `edge` leads to a contradiction.
+    Trial { edge: Edge, black: bool },
+}
+
+impl core::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Reason::Face {
+                i,
+                j,
+                number,
+                black,
+                white,
+            } => write!(
+                f,
+                "cell ({}, {}) is a {} with {} line(s) and {} cross(es)",
+                i, j, number, black, white,
+            ),
+            Reason::Vertex { i, j, black, white } => write!(
+                f,
+                "vertex ({}, {}) has {} line(s) and {} cross(es)",
+                i, j, black, white,
+            ),
+            Reason::PrematureLoop { ends } => write!(
+                f,
+                "joining {:?} and {:?} would close a loop too early",
+                ends[0], ends[1],
+            ),
+            Reason::LoopClosed { at } => write!(f, "the loop through {:?} is closed", at),
+            Reason::Pattern { pattern, i, j } => {
+                write!(f, "pattern {:?} at cell ({}, {})", pattern, i, j)
+            }
+            Reason::Trial { edge, black } => write!(
+                f,
+                "assuming a {} at {:?} leads to a contradiction",
+                if black { "line" } else { "cross" },
+                edge,
+            ),
+        }
+    }
+}
+
+/// The rule behind a [`Reason`], for counting deductions in [`Stat`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    Face,
+    Vertex,
+    PrematureLoop,
+    LoopClosed,
+    Pattern,
+    Trial,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 6] = [
+        Rule::Face,
+        Rule::Vertex,
+        Rule::PrematureLoop,
+        Rule::LoopClosed,
+        Rule::Pattern,
+        Rule::Trial,
+    ];
+}
+
+impl Reason {
+    pub fn rule(&self) -> Rule {
+        match self {
+            Reason::Face { .. } => Rule::Face,
+            Reason::Vertex { .. } => Rule::Vertex,
+            Reason::PrematureLoop { .. } => Rule::PrematureLoop,
+            Reason::LoopClosed { .. } => Rule::LoopClosed,
+            Reason::Pattern { .. } => Rule::Pattern,
+            Reason::Trial { .. } => Rule::Trial,
+        }
+    }
+}
+
+/// A single deduction: why it was made and which edges it decided.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub reason: Reason,
+    pub lines: Vec<Edge>,
+    pub crosses: Vec<Edge>,
+}
+
+impl core::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.reason)?;
+        if !self.lines.is_empty() {
+            write!(f, "; lines: {:?}", self.lines)?;
+        }
+        if !self.crosses.is_empty() {
+            write!(f, "; crosses: {:?}", self.crosses)?;
+        }
+        Ok(())
+    }
+}
+
+impl Board<'_> {
+    // Decides the undecided edges among `lines` and `crosses` and records the step if a log is kept.
+    // Returns whether any edge was decided.
+    pub(super) fn deduce(
+        &mut self,
+        stat: &mut Stat,
+        reason: Reason,
+        lines: &[Edge],
+        crosses: &[Edge],
+    ) -> bool {
+        let mut step = Step {
+            reason,
+            lines: vec![],
+            crosses: vec![],
+        };
+        let logging = self.log.is_some();
+        let mut changed = false;
+        for (edges, black) in [(lines, true), (crosses, false)] {
+            for &edge in edges {
+                if !self.is_undecided(edge) {
+                    continue;
+                }
+                self.assign(edge, black);
+                changed = true;
+                if !logging {
+                    continue;
+                }
+                if black {
+                    step.lines.push(edge);
+                } else {
+                    step.crosses.push(edge);
+                }
+            }
+        }
+        if changed {
+            stat.deductions[reason.rule() as usize] += 1;
+            if let Some(log) = &mut self.log {
+                log.push(step);
+            }
+        }
+        changed
+    }
+
+    /// Solves as far as propagation goes, recording every deduction in order.
+    /// Branching isn't attempted, so the board may be left unfinished.
+    pub fn solve_with_log(&mut self, stat: &mut Stat) -> (Vec<Step>, Result<(), Contradiction>) {
+        self.log = Some(vec![]);
+        let result = self.propagate(stat).and_then(|()| self.check_global());
+        (self.log.take().unwrap_or_default(), result)
+    }
+
+    /// Applies recorded steps to this board. Fails with the first edge that is already decided
+    /// the other way.
+    pub fn replay(&mut self, steps: &[Step]) -> Result<(), Edge> {
+        for step in steps {
+            for (edges, black) in [(&step.lines, true), (&step.crosses, false)] {
+                for &edge in edges {
+                    self.decide(edge, black)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use super::{Board, Contradiction, Edge, Reason, Stat};
+
+/// A family of deductions, from the easiest to the hardest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Technique {
+    /// Counting lines and crosses around a clue or a vertex.
+    Counting,
+    /// Local patterns, see [`super::Pattern`].
+    Patterns,
+    /// Ruling out premature loops and closing the loop.
+    Loops,
+    /// Assuming an edge and propagating until a contradiction (depth-1 trial).
+    Trial,
+}
+
+impl Technique {
+    pub const ALL: [Technique; 4] = [
+        Technique::Counting,
+        Technique::Patterns,
+        Technique::Loops,
+        Technique::Trial,
+    ];
+
+    fn of(reason: &Reason) -> Technique {
+        match reason {
+            Reason::Face { .. } | Reason::Vertex { .. } => Technique::Counting,
+            Reason::Pattern { .. } => Technique::Patterns,
+            Reason::PrematureLoop { .. } | Reason::LoopClosed { .. } => Technique::Loops,
+            Reason::Trial { .. } => Technique::Trial,
+        }
+    }
+}
+
+/// The result of [`Board::grade`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grade {
+    /// Whether the techniques were enough to finish the puzzle.
+    pub solved: bool,
+    /// How many steps of each technique were used, indexed like [`Technique::ALL`].
+    pub uses: [u64; Technique::ALL.len()],
+}
+
+impl Grade {
+    pub fn uses(&self, technique: Technique) -> u64 {
+        self.uses[technique as usize]
+    }
+
+    /// The hardest technique used, or None if nothing was deduced.
+    pub fn hardest(&self) -> Option<Technique> {
+        Technique::ALL
+            .into_iter()
+            .rev()
+            .find(|&technique| self.uses(technique) > 0)
+    }
+}
+
+impl core::fmt::Display for Grade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.solved, self.hardest()) {
+            (false, _) => write!(f, "Unsolved")?,
+            (true, None) => write!(f, "Trivial")?,
+            (true, Some(hardest)) => write!(f, "{:?}", hardest)?,
+        }
+        for technique in Technique::ALL {
+            write!(f, " {:?}={}", technique, self.uses(technique))?;
+        }
+        Ok(())
+    }
+}
+
+impl Board<'_> {
+    /// Solves with the easiest technique that still makes progress, falling back to
+    /// harder ones only when the easier ones are stuck, and counts the steps of each.
+    /// The board is left as far as the techniques got.
+    pub fn grade(&mut self) -> Grade {
+        self.log = Some(vec![]);
+        let solved = self.grade_steps().is_ok() && self.finished();
+        let mut uses = [0; Technique::ALL.len()];
+        for step in self.log.take().unwrap_or_default() {
+            uses[Technique::of(&step.reason) as usize] += 1;
+        }
+        Grade { solved, uses }
+    }
+
+    fn grade_steps(&mut self) -> Result<(), Contradiction> {
+        let mut stat = Stat::default();
+        // Trials resume after the last edge that worked, so that they don't rescan the same edges.
+        let mut next_trial = 0;
+        loop {
+            while let Some(edge) = self.pending.pop() {
+                self.examine(edge, &mut stat)?;
+            }
+            if self.apply_patterns(&mut stat)? || self.apply_loops(&mut stat)? {
+                continue;
+            }
+            let edges: Vec<_> = self.edges().collect();
+            let found = (0..edges.len())
+                .map(|k| edges[(next_trial + k) % edges.len()])
+                .enumerate()
+                .filter(|&(_, edge)| self.is_undecided(edge))
+                .find_map(|(k, edge)| {
+                    [true, false]
+                        .into_iter()
+                        .find(|&black| self.fails_with(edge, black, &mut stat))
+                        .map(|black| (k, edge, black))
+                });
+            let (k, edge, black) = if let Some(found) = found {
+                found
+            } else {
+                return self.check_global();
+            };
+            next_trial = (next_trial + k) % edges.len();
+            let (lines, crosses) = if black {
+                (vec![], vec![edge])
+            } else {
+                (vec![edge], vec![])
+            };
+            self.deduce(&mut stat, Reason::Trial { edge, black }, &lines, &crosses);
+        }
+    }
+
+    // Whether assigning the edge and propagating leads to a contradiction.
+    fn fails_with(&self, edge: Edge, black: bool, stat: &mut Stat) -> bool {
+        let mut board = self.clone();
+        board.log = None;
+        board.assign(edge, black);
+        board
+            .propagate(stat)
+            .and_then(|()| board.check_global())
+            .is_err()
+    }
+}
@@ -0,0 +1,86 @@
+use super::{Board, Contradiction, Edge, Stat, Step};
+
+/// A hint returned by [`Board::next_hint`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hint {
+    /// Every edge is decided and the loop is valid.
+    Solved,
+    /// These decided edges disagree with the unique solution.
+    Mistake(Vec<Edge>),
+    /// The board breaks the rules as it is.
+    Contradiction(Contradiction),
+    /// The simplest deduction that can be made next.
+    Step(Step),
+    /// No rule applies, but the unique solution has a line (`true`) or a cross (`false`) here.
+    Reveal(Edge, bool),
+    /// No rule applies, and the puzzle doesn't have a unique solution to reveal.
+    Stuck,
+}
+
+impl Board<'_> {
+    /// Suggests what to do next from the current state.
+    /// Mistakes are found by comparing with the unique solution, which is searched for
+    /// with at most `limit` calls.
+    pub fn next_hint(&self, limit: u64) -> Hint {
+        if self.finished() {
+            return Hint::Solved;
+        }
+        let mut solutions = Board::new(self.init).solutions(limit);
+        let solution = solutions.next();
+        let unique =
+            solution.is_some() && solutions.next().is_none() && solutions.gave_up().is_none();
+        let solution = solution.filter(|_| unique);
+        if let Some(solution) = &solution {
+            let mistakes: Vec<_> = self
+                .edges()
+                .filter(|&e| !self.is_undecided(e) && self.is_black(e) != solution.is_black(e))
+                .collect();
+            if !mistakes.is_empty() {
+                return Hint::Mistake(mistakes);
+            }
+        }
+        if let Err(contradiction) = self.check_consistency() {
+            return Hint::Contradiction(contradiction);
+        }
+        if let Some(step) = self.simplest_step() {
+            return Hint::Step(step);
+        }
+        match (solution, self.edges().find(|&e| self.is_undecided(e))) {
+            (Some(solution), Some(edge)) => Hint::Reveal(edge, solution.is_black(edge)),
+            _ => Hint::Stuck,
+        }
+    }
+
+    // Tries the rules from the simplest, in the order of Technique:
+    // clues, vertices, patterns and then loops.
+    fn simplest_step(&self) -> Option<Step> {
+        let n = self.init.len();
+        let m = self.init[0].len();
+        let mut board = self.clone();
+        board.log = Some(vec![]);
+        board.schedule_all();
+        let stat = &mut Stat::default();
+        let fired = |board: &Board| board.log.as_ref().is_some_and(|log| !log.is_empty());
+        'rules: {
+            for i in 0..n {
+                for j in 0..m {
+                    if board.apply_face(i, j, stat).is_err() || fired(&board) {
+                        break 'rules;
+                    }
+                }
+            }
+            for i in 0..n + 1 {
+                for j in 0..m + 1 {
+                    if board.apply_vertex(i, j, stat).is_err() || fired(&board) {
+                        break 'rules;
+                    }
+                }
+            }
+            if board.apply_patterns(stat).is_err() || fired(&board) {
+                break 'rules;
+            }
+            let _ = board.apply_loops(stat);
+        }
+        board.log.take()?.into_iter().next()
+    }
+}
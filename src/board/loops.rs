@@ -0,0 +1,119 @@
+use super::{Board, Contradiction, Edge, Reason, Stat};
+
+impl Board<'_> {
+    fn vertex_index(&self, (i, j): (usize, usize)) -> usize {
+        i * (self.init[0].len() + 1) + j
+    }
+    fn vertex_coord(&self, v: usize) -> (usize, usize) {
+        let m = self.init[0].len();
+        (v / (m + 1), v % (m + 1))
+    }
+
+    // Joins the paths that end at both endpoints of a new black edge,
+    // and queues the ends of the joined path for apply_loops.
+    pub(super) fn link(&mut self, edge: Edge) {
+        self.num_black += 1;
+        let [(ui, uj), (vi, vj)] = edge.endpoints();
+        let degree_u = self.vertex_black_degree(ui, uj);
+        let degree_v = self.vertex_black_degree(vi, vj);
+        if degree_u > 2 || degree_v > 2 {
+            // apply_vertex reports this.
+            return;
+        }
+        let [u, v] = edge.endpoints().map(|vertex| self.vertex_index(vertex));
+        let (a, len_a) = if degree_u == 2 {
+            (self.ends[u], self.path_len[u])
+        } else {
+            (u, 0)
+        };
+        if degree_u == 2 && a == v {
+            self.closed_loop = Some((u, len_a + 1));
+            return;
+        }
+        let (b, len_b) = if degree_v == 2 {
+            (self.ends[v], self.path_len[v])
+        } else {
+            (v, 0)
+        };
+        self.ends[a] = b;
+        self.ends[b] = a;
+        self.path_len[a] = len_a + len_b + 1;
+        self.path_len[b] = len_a + len_b + 1;
+        self.loose_ends.extend([a, b]);
+    }
+
+    // No premature loop: an edge joining both ends of a path must be white
+    // if there are black edges outside of the path.
+    // Once a loop is closed, every other edge is white.
+    // Only the path ends queued by link are checked. An end whose path holds every black edge
+    // so far stays queued, since the next black edge anywhere else makes its closing edge white.
+    // Returns whether any edge was decided.
+    pub(super) fn apply_loops(&mut self, stat: &mut Stat) -> Result<bool, Contradiction> {
+        if let Some((v, len)) = self.closed_loop {
+            if self.num_black > len {
+                return Err(self.loop_contradiction(v));
+            }
+            let rest: Vec<_> = self.edges().filter(|&e| self.is_undecided(e)).collect();
+            let at = self.vertex_coord(v);
+            return Ok(self.deduce(stat, Reason::LoopClosed { at }, &[], &rest));
+        }
+        let mut changed = false;
+        let mut loose_ends = std::mem::take(&mut self.loose_ends);
+        loose_ends.sort_unstable();
+        loose_ends.dedup();
+        loose_ends.retain(|&u| {
+            let (i, j) = self.vertex_coord(u);
+            if self.vertex_black_degree(i, j) != 1 {
+                return false;
+            }
+            let (oi, oj) = self.vertex_coord(self.ends[u]);
+            let closing = match (oi.wrapping_sub(i), oj.wrapping_sub(j)) {
+                (0, 1) => Edge::Hori(i, j),
+                (0, usize::MAX) => Edge::Hori(i, oj),
+                (1, 0) => Edge::Vert(i, j),
+                (usize::MAX, 0) => Edge::Vert(oi, j),
+                _ => return false,
+            };
+            if !self.is_undecided(closing) {
+                return false;
+            }
+            if self.num_black == self.path_len[u] {
+                return true;
+            }
+            let ends = [(i, j).min((oi, oj)), (i, j).max((oi, oj))];
+            self.deduce(stat, Reason::PrematureLoop { ends }, &[], &[closing]);
+            changed = true;
+            false
+        });
+        self.loose_ends = loose_ends;
+        Ok(changed)
+    }
+
+    // Finds a black edge outside of the closed loop through v.
+    fn loop_contradiction(&self, v: usize) -> Contradiction {
+        let mut on_loop = vec![false; self.ends.len()];
+        let mut stack = vec![v];
+        while let Some(u) = stack.pop() {
+            if on_loop[u] {
+                continue;
+            }
+            on_loop[u] = true;
+            let (i, j) = self.vertex_coord(u);
+            for edge in self.vertex_edges(i, j) {
+                if self.is_black(edge) {
+                    stack.extend(edge.endpoints().map(|w| self.vertex_index(w)));
+                }
+            }
+        }
+        let outside = self
+            .edges()
+            .filter(|&e| self.is_black(e))
+            .map(|e| e.endpoints()[0])
+            .find(|&w| !on_loop[self.vertex_index(w)]);
+        Contradiction::Global {
+            first: self.vertex_coord(v),
+            // num_black is larger than the loop, so there is such an edge.
+            second: outside.unwrap(),
+        }
+    }
+}
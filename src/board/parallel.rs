@@ -0,0 +1,167 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::{Board, Expansion, GiveUp, Interrupt, SearchResult, Stat};
+
+// How many levels of the search tree are expanded before the subtrees are handed to threads.
+// It doesn't depend on the number of threads, so neither does the result.
+const SPLIT_DEPTH: u64 = 6;
+
+// A node left after splitting the top of the search tree, in the order of a sequential search.
+enum Split<'sq> {
+    Solution(Board<'sq>),
+    Subtree(Board<'sq>),
+    // The split itself ran out of budget here.
+    GaveUp(GiveUp),
+}
+
+// What a worker found in a subtree, with the calls made until then.
+enum Outcome<'sq> {
+    Solved(Box<Board<'sq>>, u64),
+    NoSolution(u64),
+    GaveUp(GiveUp),
+}
+
+impl<'sq> Board<'sq> {
+    /// Searches for a solution with `threads` threads, or one per core if `threads` is 0.
+    /// The top of the search tree is split into subtrees that are searched in parallel.
+    /// The result is the one [`Board::search`] gives with the same `limit`, regardless of
+    /// timing or of the number of threads: calls are counted as if the subtrees were searched
+    /// one after the other. The statistics sum the work of all threads, so they may vary.
+    pub fn search_parallel(&self, limit: u64, threads: usize) -> SearchResult<'sq> {
+        let threads = if threads == 0 {
+            std::thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            threads
+        };
+        let mut stat = Stat::default();
+        // Each split comes with the calls that a sequential search makes before reaching it
+        // within the split.
+        let mut splits = vec![];
+        let depth = self.depth + SPLIT_DEPTH;
+        self.clone().split(&mut stat, limit, depth, &mut splits);
+        let subtrees: Vec<_> = splits
+            .iter()
+            .enumerate()
+            .filter_map(|(k, (split, before))| match split {
+                Split::Subtree(board) => Some((k, board, *before)),
+                _ => None,
+            })
+            .collect();
+        // The position of the first solution found so far. Subtrees after it are abandoned.
+        let first = Arc::new(AtomicUsize::new(splits.len()));
+        let next = AtomicUsize::new(0);
+        let outcomes = Mutex::new(splits.iter().map(|_| None).collect::<Vec<_>>());
+        let stats = Mutex::new(vec![]);
+        std::thread::scope(|scope| {
+            for _ in 0..threads.min(subtrees.len()) {
+                scope.spawn(|| {
+                    let mut stat = Stat::default();
+                    while let Some(&(k, board, before)) =
+                        subtrees.get(next.fetch_add(1, Ordering::Relaxed))
+                    {
+                        if first.load(Ordering::Relaxed) < k {
+                            continue;
+                        }
+                        let mut board = board.clone();
+                        board.abort = Some((first.clone(), k));
+                        // Earlier subtrees may take some of this budget, which is settled below.
+                        let budget = limit.saturating_sub(before);
+                        let outcome = board.search_subtree(&mut stat, budget);
+                        if let Outcome::Solved(..) = outcome {
+                            first.fetch_min(k, Ordering::Relaxed);
+                        }
+                        outcomes.lock().unwrap()[k] = Some(outcome);
+                    }
+                    stats.lock().unwrap().push(stat);
+                });
+            }
+        });
+        for other in stats.into_inner().unwrap() {
+            stat.merge(&other);
+        }
+        // Replays the sequential search: the calls before a split are those of the split
+        // up to it plus those of the subtrees before it.
+        let mut outcomes = outcomes.into_inner().unwrap();
+        let mut earlier = 0;
+        for ((split, before), outcome) in splits.into_iter().zip(&mut outcomes) {
+            let start = before + earlier;
+            let (solution, calls) = match (split, outcome.take()) {
+                (Split::GaveUp(reason), _) => return SearchResult::GaveUp(reason, stat),
+                (Split::Solution(board), _) => (Some(Box::new(board)), 1),
+                (_, Some(Outcome::Solved(board, calls))) => (Some(board), calls),
+                (_, Some(Outcome::NoSolution(calls))) => (None, calls),
+                (_, Some(Outcome::GaveUp(reason))) => return SearchResult::GaveUp(reason, stat),
+                // Subtrees are only skipped after a solution earlier on.
+                (_, None) => unreachable!(),
+            };
+            if start + calls > limit {
+                return SearchResult::GaveUp(GiveUp::Limit, stat);
+            }
+            if let Some(board) = solution {
+                return SearchResult::Solved(board, stat);
+            }
+            earlier += calls;
+        }
+        SearchResult::NoSolution(stat)
+    }
+
+    // Expands the search tree down to `depth` depth-first, like search_all, and
+    // leaves the nodes there as subtrees. Stops at the first solution.
+    // Returns whether the split should go on.
+    fn split(
+        mut self,
+        stat: &mut Stat,
+        limit: u64,
+        depth: u64,
+        splits: &mut Vec<(Split<'sq>, u64)>,
+    ) -> bool {
+        let before = stat.num_call;
+        if self.depth == depth {
+            splits.push((Split::Subtree(self), before));
+            return true;
+        }
+        if let Err(reason) = self.check_budget(stat, limit) {
+            splits.push((Split::GaveUp(reason), before));
+            return false;
+        }
+        stat.num_call += 1;
+        match self.expand(stat) {
+            Expansion::Solved => {
+                splits.push((Split::Solution(self), before));
+                false
+            }
+            Expansion::Dead => true,
+            Expansion::Branch(edge) => {
+                stat.num_branch += 1;
+                self.depth += 1;
+                let mut white = self.clone();
+                white.assign(edge, false);
+                if !white.split(stat, limit, depth, splits) {
+                    return false;
+                }
+                self.assign(edge, true);
+                self.split(stat, limit, depth, splits)
+            }
+        }
+    }
+
+    fn search_subtree(self, stat: &mut Stat, limit: u64) -> Outcome<'sq> {
+        let mut solution = None;
+        // The calls of this subtree alone, which the budget applies to.
+        let mut local = Stat::default();
+        let result = self.search_all(&mut local, limit, &mut |board| {
+            solution = Some(board.clone());
+            true
+        });
+        stat.merge(&local);
+        match (result, solution) {
+            (_, Some(mut board)) => {
+                board.abort = None;
+                Outcome::Solved(Box::new(board), local.num_call)
+            }
+            (Err(Interrupt::GaveUp(reason)), None) => Outcome::GaveUp(reason),
+            _ => Outcome::NoSolution(local.num_call),
+        }
+    }
+}
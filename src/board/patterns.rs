@@ -0,0 +1,334 @@
+use super::{Board, Contradiction, Edge, Reason, Stat};
+
+/// A classic local pattern. Each pattern is applied under all rotations and reflections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pattern {
+    /// Two orthogonally adjacent 3s: the edge between them and the two opposite edges are lines,
+    /// and the edges continuing the middle line are crosses.
+    /// Only applied if some other clue rules out the small loop around the two 3s.
+    AdjacentThrees,
+    /// Two diagonally adjacent 3s: the two edges at the far corner of each 3 are lines.
+    DiagonalThrees,
+    /// A 3 at a corner whose two outer edges are crosses, e.g. a 3 in a corner of the board:
+    /// both edges of the 3 at that corner are lines.
+    CornerThree,
+    /// A 1 at a corner whose two outer edges are crosses: both edges of the 1 at that corner are crosses.
+    CornerOne,
+    /// A 2 at a corner whose two outer edges are crosses, next to a border:
+    /// the line along the border beyond the 2 is forced.
+    CornerTwo,
+    /// A line entering a corner of a 3: the other outer edge at that corner is a cross,
+    /// and the two far edges of the 3 are lines.
+    LineIntoThree,
+    /// A line entering a corner of a 1 whose other outer edge is a cross:
+    /// the two far edges of the 1 are crosses.
+    LineIntoOne,
+}
+
+impl Pattern {
+    pub const ALL: [Pattern; 7] = [
+        Pattern::AdjacentThrees,
+        Pattern::DiagonalThrees,
+        Pattern::CornerThree,
+        Pattern::CornerOne,
+        Pattern::CornerTwo,
+        Pattern::LineIntoThree,
+        Pattern::LineIntoOne,
+    ];
+
+    pub(crate) fn index(self) -> usize {
+        self as usize
+    }
+}
+
+// A pattern in doubled coordinates relative to the center of an anchor cell:
+// cells are at (even, even), vertices at (odd, odd) and edges at the rest,
+// e.g. (-1, 0) is the top edge of the anchor cell and (0, 2) is the cell to its right.
+// Edges outside of the board count as crosses.
+struct Definition {
+    pattern: Pattern,
+    clues: &'static [(i32, i32, u8)],
+    lines: &'static [(i32, i32)],
+    crosses: &'static [(i32, i32)],
+    then_lines: &'static [(i32, i32)],
+    then_crosses: &'static [(i32, i32)],
+    // Whether the conclusion relies on the loop not being the small loop around the clues.
+    needs_distant_clue: bool,
+}
+
+const DEFINITIONS: [Definition; 7] = [
+    Definition {
+        pattern: Pattern::AdjacentThrees,
+        clues: &[(0, 0, 3), (0, 2, 3)],
+        lines: &[],
+        crosses: &[],
+        then_lines: &[(0, -1), (0, 1), (0, 3)],
+        then_crosses: &[(-2, 1), (2, 1)],
+        needs_distant_clue: true,
+    },
+    Definition {
+        pattern: Pattern::DiagonalThrees,
+        clues: &[(0, 0, 3), (2, 2, 3)],
+        lines: &[],
+        crosses: &[],
+        then_lines: &[(-1, 0), (0, -1), (3, 2), (2, 3)],
+        then_crosses: &[],
+        needs_distant_clue: false,
+    },
+    Definition {
+        pattern: Pattern::CornerThree,
+        clues: &[(0, 0, 3)],
+        lines: &[],
+        crosses: &[(-1, -2), (-2, -1)],
+        then_lines: &[(-1, 0), (0, -1)],
+        then_crosses: &[],
+        needs_distant_clue: false,
+    },
+    Definition {
+        pattern: Pattern::CornerOne,
+        clues: &[(0, 0, 1)],
+        lines: &[],
+        crosses: &[(-1, -2), (-2, -1)],
+        then_lines: &[],
+        then_crosses: &[(-1, 0), (0, -1)],
+        needs_distant_clue: false,
+    },
+    Definition {
+        pattern: Pattern::CornerTwo,
+        clues: &[(0, 0, 2)],
+        lines: &[],
+        crosses: &[(-1, -2), (-2, -1), (-2, 1)],
+        then_lines: &[(-1, 2)],
+        then_crosses: &[],
+        needs_distant_clue: false,
+    },
+    Definition {
+        pattern: Pattern::LineIntoThree,
+        clues: &[(0, 0, 3)],
+        lines: &[(-1, -2)],
+        crosses: &[],
+        then_lines: &[(1, 0), (0, 1)],
+        then_crosses: &[(-2, -1)],
+        needs_distant_clue: false,
+    },
+    Definition {
+        pattern: Pattern::LineIntoOne,
+        clues: &[(0, 0, 1)],
+        lines: &[(-1, -2)],
+        crosses: &[(-2, -1)],
+        then_lines: &[],
+        then_crosses: &[(1, 0), (0, 1)],
+        needs_distant_clue: false,
+    },
+];
+
+// How far the lines and crosses required by any definition lie from its anchor,
+// in doubled coordinates.
+const REACH: i32 = reach(false);
+// How far any edge that a definition requires or decides lies from its anchor.
+const SPAN: i32 = reach(true);
+
+const fn reach(conclusions: bool) -> i32 {
+    let mut reach = 0;
+    let mut k = 0;
+    while k < DEFINITIONS.len() {
+        let offsets = [
+            DEFINITIONS[k].lines,
+            DEFINITIONS[k].crosses,
+            DEFINITIONS[k].then_lines,
+            DEFINITIONS[k].then_crosses,
+        ];
+        let count = if conclusions { offsets.len() } else { 2 };
+        let mut c = 0;
+        while c < count {
+            let mut e = 0;
+            while e < offsets[c].len() {
+                let (y, x) = offsets[c][e];
+                let distance = if y.abs() > x.abs() { y.abs() } else { x.abs() };
+                if distance > reach {
+                    reach = distance;
+                }
+                e += 1;
+            }
+            c += 1;
+        }
+        k += 1;
+    }
+    reach
+}
+
+// The 8 rotations and reflections of the plane.
+fn transform(k: usize, (y, x): (i32, i32)) -> (i32, i32) {
+    let (y, x) = if k & 4 != 0 { (x, y) } else { (y, x) };
+    let y = if k & 2 != 0 { -y } else { y };
+    let x = if k & 1 != 0 { -x } else { x };
+    (y, x)
+}
+
+impl Board<'_> {
+    /// Enables or disables a pattern. All patterns are enabled by default.
+    pub fn set_pattern(&mut self, pattern: Pattern, enabled: bool) {
+        if enabled {
+            if (self.patterns & 1 << pattern.index()) == 0 {
+                self.unscanned = self.edges().collect();
+            }
+            self.patterns |= 1 << pattern.index();
+        } else {
+            self.patterns &= !(1 << pattern.index());
+        }
+    }
+
+    // The edge at doubled coordinates (y, x), or None if it is outside of the board.
+    fn edge_at(&self, y: i32, x: i32) -> Option<Edge> {
+        let n = self.init.len() as i32;
+        let m = self.init[0].len() as i32;
+        if y < 0 || x < 0 || y > 2 * n || x > 2 * m {
+            return None;
+        }
+        let (i, j) = (y as usize / 2, x as usize / 2);
+        match (y % 2, x % 2) {
+            (0, 1) => Some(Edge::Hori(i, j)),
+            (1, 0) => Some(Edge::Vert(i, j)),
+            _ => None,
+        }
+    }
+
+    // The cells whose patterns may require the edge.
+    fn pattern_anchors(&self, edge: Edge) -> impl Iterator<Item = (usize, usize)> {
+        let n = self.init.len() as i32;
+        let m = self.init[0].len() as i32;
+        let (y, x) = match edge {
+            Edge::Hori(i, j) => (2 * i as i32, 2 * j as i32 + 1),
+            Edge::Vert(i, j) => (2 * i as i32 + 1, 2 * j as i32),
+        };
+        // The centers 2 * i + 1 within REACH of y, and likewise for x.
+        let rows = (y - REACH).div_euclid(2).max(0)..=(y + REACH - 1).div_euclid(2).min(n - 1);
+        let columns = (x - REACH).div_euclid(2).max(0)..=(x + REACH - 1).div_euclid(2).min(m - 1);
+        rows.flat_map(move |i| columns.clone().map(move |j| (i as usize, j as usize)))
+    }
+
+    // The edges that a pattern anchored at the cell (i, j) may require or decide.
+    pub(super) fn pattern_edges(&self, i: usize, j: usize) -> Vec<Edge> {
+        let (cy, cx) = (2 * i as i32 + 1, 2 * j as i32 + 1);
+        (cy - SPAN..=cy + SPAN)
+            .flat_map(|y| (cx - SPAN..=cx + SPAN).map(move |x| (y, x)))
+            .filter_map(|(y, x)| self.edge_at(y, x))
+            .collect()
+    }
+
+    // Applies every enabled pattern at the cells around the edges decided since the last call.
+    // Returns whether any edge was decided.
+    pub(super) fn apply_patterns(&mut self, stat: &mut Stat) -> Result<bool, Contradiction> {
+        let unscanned = std::mem::take(&mut self.unscanned);
+        if self.patterns == 0 {
+            return Ok(false);
+        }
+        let mut cells: Vec<_> = unscanned
+            .into_iter()
+            .flat_map(|edge| self.pattern_anchors(edge))
+            .collect();
+        cells.sort_unstable();
+        cells.dedup();
+        let mut changed = false;
+        for (i, j) in cells {
+            let anchor = if let Some(number) = self.init[i][j].get_number() {
+                number
+            } else {
+                continue;
+            };
+            for def in &DEFINITIONS {
+                if (self.patterns & 1 << def.pattern.index()) == 0 || def.clues[0].2 != anchor {
+                    continue;
+                }
+                for k in 0..8 {
+                    if self.apply_pattern(def, k, i, j, stat)? {
+                        stat.pattern_hits[def.pattern.index()] += 1;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    fn apply_pattern(
+        &mut self,
+        def: &Definition,
+        k: usize,
+        i: usize,
+        j: usize,
+        stat: &mut Stat,
+    ) -> Result<bool, Contradiction> {
+        let (cy, cx) = (2 * i as i32 + 1, 2 * j as i32 + 1);
+        let at = |offset| {
+            let (dy, dx) = transform(k, offset);
+            (cy + dy, cx + dx)
+        };
+        let n = self.init.len() as i32;
+        let m = self.init[0].len() as i32;
+        for &(dy, dx, number) in def.clues {
+            let (y, x) = at((dy, dx));
+            if y < 0 || x < 0 || y >= 2 * n || x >= 2 * m {
+                return Ok(false);
+            }
+            if self.init[y as usize / 2][x as usize / 2].get_number() != Some(number) {
+                return Ok(false);
+            }
+        }
+        for &offset in def.lines {
+            let (y, x) = at(offset);
+            if !self.edge_at(y, x).is_some_and(|e| self.is_black(e)) {
+                return Ok(false);
+            }
+        }
+        for &offset in def.crosses {
+            let (y, x) = at(offset);
+            if !self.edge_at(y, x).is_none_or(|e| self.is_white(e)) {
+                return Ok(false);
+            }
+        }
+        if def.needs_distant_clue {
+            // A positive clue two or more cells away from every clue of the pattern
+            // needs a line that the small loop around the pattern doesn't provide.
+            // There is one if some positive clue lies outside of the box one cell around
+            // the pattern. For clues forming a rectangle, like two adjacent 3s, that is exact.
+            let (mut top, mut left) = (usize::MAX, usize::MAX);
+            let (mut bottom, mut right) = (0, 0);
+            for &(dy, dx, _) in def.clues {
+                let (y, x) = at((dy, dx));
+                let (i, j) = (y as usize / 2, x as usize / 2);
+                (top, left) = (top.min(i), left.min(j));
+                (bottom, right) = (bottom.max(i), right.max(j));
+            }
+            let distant = self.positive_clues.is_some_and(|[(ti, tj), (bi, bj)]| {
+                ti + 1 < top || tj + 1 < left || bi > bottom + 1 || bj > right + 1
+            });
+            if !distant {
+                return Ok(false);
+            }
+        }
+        let lines: Vec<_> = def
+            .then_lines
+            .iter()
+            .filter_map(|&offset| self.edge_at(at(offset).0, at(offset).1))
+            .collect();
+        let crosses: Vec<_> = def
+            .then_crosses
+            .iter()
+            .filter_map(|&offset| self.edge_at(at(offset).0, at(offset).1))
+            .collect();
+        if lines.iter().any(|&e| self.is_white(e)) || crosses.iter().any(|&e| self.is_black(e)) {
+            return Err(Contradiction::Pattern {
+                pattern: def.pattern,
+                i,
+                j,
+            });
+        }
+        let reason = Reason::Pattern {
+            pattern: def.pattern,
+            i,
+            j,
+        };
+        Ok(self.deduce(stat, reason, &lines, &crosses))
+    }
+}
@@ -0,0 +1,125 @@
+use super::{Board, Contradiction, Edge, Reason, Stat};
+
+impl Board<'_> {
+    // Examines the faces and vertices around pending edges until nothing changes.
+    // Edges decided on the way are queued again, so this runs to a fixpoint.
+    pub(super) fn propagate(&mut self, stat: &mut Stat) -> Result<(), Contradiction> {
+        stat.num_propagate += 1;
+        let result = self.run_to_fixpoint(stat);
+        if result.is_err() {
+            self.pending.clear();
+            self.unscanned.clear();
+            self.loose_ends.clear();
+        }
+        result
+    }
+
+    fn run_to_fixpoint(&mut self, stat: &mut Stat) -> Result<(), Contradiction> {
+        loop {
+            while let Some(edge) = self.pending.pop() {
+                self.examine(edge, stat)?;
+            }
+            if !self.apply_loops(stat)? && !self.apply_patterns(stat)? {
+                return Ok(());
+            }
+        }
+    }
+
+    pub(super) fn examine(&mut self, edge: Edge, stat: &mut Stat) -> Result<(), Contradiction> {
+        for (i, j) in edge.endpoints() {
+            self.apply_vertex(i, j, stat)?;
+        }
+        for (i, j) in self.edge_faces(edge) {
+            self.apply_face(i, j, stat)?;
+        }
+        Ok(())
+    }
+
+    // A clue is satisfied by filling the rest with black or white once it is tight.
+    pub(super) fn apply_face(
+        &mut self,
+        i: usize,
+        j: usize,
+        stat: &mut Stat,
+    ) -> Result<(), Contradiction> {
+        let number = if let Some(number) = self.init[i][j].get_number() {
+            number
+        } else {
+            return Ok(());
+        };
+        let edges = Self::face_edges(i, j);
+        let black = edges.iter().filter(|&&e| self.is_black(e)).count() as u32;
+        let white = edges.iter().filter(|&&e| self.is_white(e)).count() as u32;
+        let black_limit = 4 - white;
+        let target = u32::from(number);
+        if target < black || black_limit < target {
+            return Err(Contradiction::Face {
+                i,
+                j,
+                number,
+                black,
+                black_limit,
+            });
+        }
+        let reason = Reason::Face {
+            i,
+            j,
+            number,
+            black,
+            white,
+        };
+        if target == black_limit && target > black {
+            // fill with black
+            self.deduce(stat, reason, &edges, &[]);
+        } else if target == black && target < black_limit {
+            // fill with white
+            self.deduce(stat, reason, &[], &edges);
+        }
+        Ok(())
+    }
+
+    // Every vertex has degree 0 or 2.
+    pub(super) fn apply_vertex(
+        &mut self,
+        i: usize,
+        j: usize,
+        stat: &mut Stat,
+    ) -> Result<(), Contradiction> {
+        let mut edges = [Edge::Hori(0, 0); 4];
+        let mut len = 0;
+        for edge in self.vertex_edges(i, j) {
+            edges[len] = edge;
+            len += 1;
+        }
+        let edges = &edges[..len];
+        let black = edges.iter().filter(|&&e| self.is_black(e)).count() as u32;
+        let black_limit = edges.iter().filter(|&&e| !self.is_white(e)).count() as u32;
+        if [0, 2].iter().all(|&x| x < black || x > black_limit) {
+            return Err(Contradiction::Vertex {
+                i,
+                j,
+                black,
+                black_limit,
+            });
+        }
+        let reason = Reason::Vertex {
+            i,
+            j,
+            black,
+            white: len as u32 - black_limit,
+        };
+        // 0?
+        if black == 0 && black_limit == 1 {
+            self.deduce(stat, reason, &[], edges);
+        }
+        // 2?
+        if black == 1 && black_limit == 2 {
+            self.deduce(stat, reason, edges, &[]);
+        }
+        // 2?
+        if black == 2 && black_limit > 2 {
+            self.deduce(stat, reason, &[], edges);
+        }
+        Ok(())
+    }
+}
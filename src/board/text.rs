@@ -0,0 +1,175 @@
+use super::{Board, Edge};
+use crate::square::Square;
+
+/// The reason why a board in the text form printed by `Display` was rejected.
+/// Lines and columns are counted from 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextError {
+    /// The text doesn't have an odd number of at least 3 lines.
+    InvalidHeight { lines: usize },
+    /// The first line isn't `+` followed by `width` pairs of an edge and `+`.
+    InvalidWidth { length: usize },
+    /// Line `line` is longer than the first line.
+    TooLong { line: usize },
+    /// `character` at (`line`, `column`) can't appear there.
+    InvalidCharacter {
+        line: usize,
+        column: usize,
+        character: char,
+    },
+    /// The text has a different size from the clues it is loaded onto.
+    SizeMismatch {
+        expected: (usize, usize),
+        actual: (usize, usize),
+    },
+    /// The clue of the cell (i, j) differs from the clues it is loaded onto.
+    ClueMismatch { i: usize, j: usize },
+}
+
+impl core::fmt::Display for TextError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            TextError::InvalidHeight { lines } => {
+                write!(
+                    f,
+                    "a board needs an odd number of lines, but there are {}",
+                    lines
+                )
+            }
+            TextError::InvalidWidth { length } => {
+                write!(
+                    f,
+                    "the first line has length {}, which isn't 2 * width + 1",
+                    length
+                )
+            }
+            TextError::TooLong { line } => write!(f, "line {} is longer than the first line", line),
+            TextError::InvalidCharacter {
+                line,
+                column,
+                character,
+            } => write!(
+                f,
+                "unexpected character {:?} at line {}, column {}",
+                character, line, column,
+            ),
+            TextError::SizeMismatch { expected, actual } => write!(
+                f,
+                "expected a {}x{} board, but the text is {}x{}",
+                expected.0, expected.1, actual.0, actual.1,
+            ),
+            TextError::ClueMismatch { i, j } => {
+                write!(f, "the clue of cell ({}, {}) doesn't match", i, j)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextError {}
+
+// A board in text form, checked character by character.
+struct Text {
+    height: usize,
+    width: usize,
+    clues: Vec<Vec<Square>>,
+    // For each edge, Some(true) for a line, Some(false) for a cross and None if undecided.
+    edges: Vec<(Edge, Option<bool>)>,
+}
+
+fn parse_text(text: &str) -> Result<Text, TextError> {
+    let lines: Vec<Vec<char>> = text
+        .trim_end_matches(['\n', '\r'])
+        .lines()
+        .map(|line| line.chars().collect())
+        .collect();
+    if lines.len() < 3 || lines.len().is_multiple_of(2) {
+        return Err(TextError::InvalidHeight { lines: lines.len() });
+    }
+    let length = lines[0].len();
+    if length < 3 || length.is_multiple_of(2) {
+        return Err(TextError::InvalidWidth { length });
+    }
+    let (height, width) = (lines.len() / 2, length / 2);
+    let mut text = Text {
+        height,
+        width,
+        clues: vec![vec![Square::Blank; width]; height],
+        edges: vec![],
+    };
+    for (y, line) in lines.iter().enumerate() {
+        if line.len() > length {
+            return Err(TextError::TooLong { line: y });
+        }
+        for x in 0..length {
+            // Trailing spaces may be left out.
+            let character = line.get(x).copied().unwrap_or(' ');
+            let invalid = TextError::InvalidCharacter {
+                line: y,
+                column: x,
+                character,
+            };
+            let (i, j) = (y / 2, x / 2);
+            match (y % 2, x % 2) {
+                (0, 0) if character == '+' => {}
+                (1, 1) => match character {
+                    ' ' => {}
+                    '0'..='4' => {
+                        text.clues[i][j] = Square::new_number(character as u8 - b'0').unwrap()
+                    }
+                    _ => return Err(invalid),
+                },
+                (0, 0) => return Err(invalid),
+                (parity, _) => {
+                    let edge = if parity == 0 {
+                        Edge::Hori(i, j)
+                    } else {
+                        Edge::Vert(i, j)
+                    };
+                    let state = match (character, parity) {
+                        (' ', _) => None,
+                        ('.', _) => Some(false),
+                        ('-', 0) | ('|', 1) => Some(true),
+                        _ => return Err(invalid),
+                    };
+                    text.edges.push((edge, state));
+                }
+            }
+        }
+    }
+    Ok(text)
+}
+
+/// Reads the clues of a board in the text form printed by `Display`, ignoring its edges.
+pub fn parse_clues(text: &str) -> Result<Vec<Vec<Square>>, TextError> {
+    parse_text(text).map(|text| text.clues)
+}
+
+impl<'sq> Board<'sq> {
+    /// Restores a board from the text form printed by `Display`:
+    /// `-` and `|` are lines, `.` are crosses and spaces are undecided.
+    /// The clues in the text must match `init`, e.g. as read by [`parse_clues`].
+    pub fn load(init: &'sq [Vec<Square>], text: &str) -> Result<Self, TextError> {
+        let text = parse_text(text)?;
+        let expected = (init.len(), init[0].len());
+        if (text.height, text.width) != expected {
+            return Err(TextError::SizeMismatch {
+                expected,
+                actual: (text.height, text.width),
+            });
+        }
+        let mismatch = (0..text.height)
+            .flat_map(|i| (0..text.width).map(move |j| (i, j)))
+            .find(|&(i, j)| text.clues[i][j] != init[i][j]);
+        if let Some((i, j)) = mismatch {
+            return Err(TextError::ClueMismatch { i, j });
+        }
+        let mut board = Board::new(init);
+        for (edge, state) in text.edges {
+            if let Some(black) = state {
+                // This also joins paths and schedules the edge for propagation.
+                board.assign(edge, black);
+            }
+        }
+        Ok(board)
+    }
+}
@@ -0,0 +1,305 @@
+use crate::board::{Board, SolutionCount, Technique};
+use crate::square::Square;
+
+/// A small xorshift generator, so that puzzles are reproducible from a seed.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64, so that close seeds give unrelated states and the state is never 0.
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Rng((z ^ (z >> 31)) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in 0..n. n must be positive.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for k in (1..items.len()).rev() {
+            items.swap(k, self.below(k + 1));
+        }
+    }
+}
+
+// How many random loops generate tries before giving up on the target.
+const MAX_ATTEMPTS: usize = 100;
+
+/// Generates a `width` x `height` puzzle with exactly one solution whose hardest technique
+/// in [`Board::grade`] is `target`. The same arguments always give the same puzzle.
+/// [`crate::square::to_puzz_link`] turns it into a URL for other tools.
+/// Returns None if no such puzzle turns up, which happens on boards too small for `target`.
+pub fn generate(
+    width: usize,
+    height: usize,
+    seed: u64,
+    target: Technique,
+) -> Option<Vec<Vec<Square>>> {
+    assert!(width > 0 && height > 0, "the board must not be empty");
+    let mut rng = Rng::new(seed);
+    let within_target = |puzzle: &[Vec<Square>]| {
+        let grade = Board::new(puzzle).grade();
+        grade.solved && grade.hardest().is_none_or(|hardest| hardest <= target)
+    };
+    for _ in 0..MAX_ATTEMPTS {
+        let inside = random_region(width, height, &mut rng);
+        let full = clues_of(&inside);
+        // Rarely, even all the clues don't pin down the loop.
+        if !within_target(&full) {
+            continue;
+        }
+        let mut cells: Vec<_> = (0..height)
+            .flat_map(|i| (0..width).map(move |j| vec![(i, j)]))
+            .collect();
+        rng.shuffle(&mut cells);
+        // Removing clues as long as the puzzle stays within the target makes it harder,
+        // but it may still fall short of the target.
+        let puzzle = remove_clues(full, &cells, within_target);
+        if Board::new(&puzzle).grade().hardest() == Some(target) {
+            return Some(puzzle);
+        }
+    }
+    None
+}
+
+/// Removes redundant clues from a puzzle with a unique solution, until removing any other clue
+/// would allow a second solution. With `symmetric`, clues are removed in pairs of cells that
+/// map to each other under 180° rotation, so a symmetric layout stays symmetric.
+/// Each uniqueness check searches with at most `limit` calls, and a clue whose check
+/// hits the limit is kept. Returns None if the puzzle itself isn't known to be unique.
+pub fn minimize(puzzle: &[Vec<Square>], symmetric: bool, limit: u64) -> Option<Vec<Vec<Square>>> {
+    let unique = |puzzle: &[Vec<Square>]| {
+        let count = Board::new(puzzle).count_solutions(limit, 2).0;
+        count == SolutionCount::Exactly(1)
+    };
+    if !unique(puzzle) {
+        return None;
+    }
+    let height = puzzle.len();
+    let width = puzzle[0].len();
+    let groups: Vec<_> = (0..height)
+        .flat_map(|i| (0..width).map(move |j| (i, j)))
+        .filter_map(|(i, j)| {
+            if !symmetric {
+                return Some(vec![(i, j)]);
+            }
+            let opposite = (height - 1 - i, width - 1 - j);
+            // Each pair is listed once, from its first cell in row-major order.
+            match (i, j).cmp(&opposite) {
+                std::cmp::Ordering::Less => Some(vec![(i, j), opposite]),
+                std::cmp::Ordering::Equal => Some(vec![(i, j)]),
+                std::cmp::Ordering::Greater => None,
+            }
+        })
+        .collect();
+    Some(remove_clues(puzzle.to_vec(), &groups, unique))
+}
+
+/// Removes the clues of each group of cells in turn, unless the puzzle stops satisfying `keep`.
+/// The puzzle must satisfy `keep` in the first place.
+pub(crate) fn remove_clues<F>(
+    mut puzzle: Vec<Vec<Square>>,
+    groups: &[Vec<(usize, usize)>],
+    mut keep: F,
+) -> Vec<Vec<Square>>
+where
+    F: FnMut(&[Vec<Square>]) -> bool,
+{
+    for group in groups {
+        let saved: Vec<_> = group.iter().map(|&(i, j)| puzzle[i][j]).collect();
+        if saved.iter().all(|&square| square == Square::Blank) {
+            continue;
+        }
+        for &(i, j) in group {
+            puzzle[i][j] = Square::Blank;
+        }
+        if !keep(&puzzle) {
+            for (&(i, j), &square) in group.iter().zip(&saved) {
+                puzzle[i][j] = square;
+            }
+        }
+    }
+    puzzle
+}
+
+// Grows a random set of cells whose boundary is a single loop:
+// the cells stay connected, the other cells stay connected to the outside of the board,
+// and no two cells touch only at a corner.
+fn random_region(width: usize, height: usize, rng: &mut Rng) -> Vec<Vec<bool>> {
+    let mut inside = vec![vec![false; width]; height];
+    inside[rng.below(height)][rng.below(width)] = true;
+    let size = width * height;
+    let goal = (size * (40 + rng.below(31)) / 100).max(1);
+    for _ in 1..goal {
+        let mut candidates: Vec<_> = (0..height)
+            .flat_map(|i| (0..width).map(move |j| (i, j)))
+            .filter(|&(i, j)| {
+                !inside[i][j] && neighbors(width, height, i, j).any(|(y, x)| inside[y][x])
+            })
+            .collect();
+        rng.shuffle(&mut candidates);
+        let grown = candidates.into_iter().find(|&(i, j)| {
+            inside[i][j] = true;
+            if !has_pinch(&inside, i, j) && outside_connected(&inside) {
+                return true;
+            }
+            inside[i][j] = false;
+            false
+        });
+        if grown.is_none() {
+            break;
+        }
+    }
+    inside
+}
+
+fn neighbors(
+    width: usize,
+    height: usize,
+    i: usize,
+    j: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    [(0, 1), (2, 1), (1, 0), (1, 2)]
+        .into_iter()
+        .filter_map(move |(di, dj)| Some(((i + di).checked_sub(1)?, (j + dj).checked_sub(1)?)))
+        .filter(move |&(y, x)| y < height && x < width)
+}
+
+// Whether one of the 2x2 blocks around (i, j) is a checkerboard. Cells beyond the border are outside.
+fn has_pinch(inside: &[Vec<bool>], i: usize, j: usize) -> bool {
+    let at = |y: isize, x: isize| {
+        y >= 0
+            && x >= 0
+            && inside
+                .get(y as usize)
+                .and_then(|row| row.get(x as usize))
+                .copied()
+                .unwrap_or(false)
+    };
+    let (i, j) = (i as isize, j as isize);
+    [(-1, -1), (-1, 0), (0, -1), (0, 0)]
+        .iter()
+        .any(|&(di, dj)| {
+            let (y, x) = (i + di, j + dj);
+            let (a, b, c, d) = (at(y, x), at(y, x + 1), at(y + 1, x), at(y + 1, x + 1));
+            a == d && b == c && a != b
+        })
+}
+
+// Whether every cell outside of the region reaches the border through cells outside of the region.
+fn outside_connected(inside: &[Vec<bool>]) -> bool {
+    let height = inside.len();
+    let width = inside[0].len();
+    let mut seen = vec![vec![false; width]; height];
+    let mut stack: Vec<_> = (0..height)
+        .flat_map(|i| (0..width).map(move |j| (i, j)))
+        .filter(|&(i, j)| i == 0 || j == 0 || i == height - 1 || j == width - 1)
+        .filter(|&(i, j)| !inside[i][j])
+        .collect();
+    while let Some((i, j)) = stack.pop() {
+        if seen[i][j] {
+            continue;
+        }
+        seen[i][j] = true;
+        stack.extend(neighbors(width, height, i, j).filter(|&(y, x)| !inside[y][x]));
+    }
+    (0..height).all(|i| (0..width).all(|j| inside[i][j] || seen[i][j]))
+}
+
+// Every clue: the number of sides of a cell that separate it from the other side of the loop.
+fn clues_of(inside: &[Vec<bool>]) -> Vec<Vec<Square>> {
+    let height = inside.len();
+    let width = inside[0].len();
+    (0..height)
+        .map(|i| {
+            (0..width)
+                .map(|j| {
+                    let same = neighbors(width, height, i, j)
+                        .filter(|&(y, x)| inside[y][x] == inside[i][j])
+                        .count();
+                    // Sides on the border separate an inside cell from the outside.
+                    let border = 4 - neighbors(width, height, i, j).count();
+                    let number = 4 - same - if inside[i][j] { 0 } else { border };
+                    Square::new_number(number as u8).unwrap()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        for target in Technique::ALL {
+            let puzzle = generate(7, 5, 42, target).unwrap();
+            assert_eq!(puzzle.len(), 5);
+            assert!(puzzle.iter().all(|row| row.len() == 7));
+            assert_eq!(Some(&puzzle), generate(7, 5, 42, target).as_ref());
+            let grade = Board::new(&puzzle).grade();
+            assert!(grade.solved);
+            assert_eq!(grade.hardest(), Some(target));
+            let count = Board::new(&puzzle).count_solutions(1_000_000, 2).0;
+            assert_eq!(count, SolutionCount::Exactly(1));
+        }
+        // A single cell has no room for patterns.
+        assert_eq!(generate(1, 1, 42, Technique::Patterns), None);
+    }
+
+    #[test]
+    fn test_minimize() {
+        let unique = |puzzle: &[Vec<Square>]| {
+            let count = Board::new(puzzle).count_solutions(1_000_000, 2).0;
+            count == SolutionCount::Exactly(1)
+        };
+        let puzzle = clues_of(&random_region(6, 5, &mut Rng::new(3)));
+        let minimal = minimize(&puzzle, false, 1_000_000).unwrap();
+        assert!(unique(&minimal));
+        // The solution of the original puzzle still fits, so it is the one left.
+        assert!(puzzle
+            .iter()
+            .flatten()
+            .zip(minimal.iter().flatten())
+            .all(|(&a, &b)| b == Square::Blank || a == b));
+        for i in 0..minimal.len() {
+            for j in 0..minimal[0].len() {
+                if minimal[i][j] == Square::Blank {
+                    continue;
+                }
+                let mut fewer = minimal.clone();
+                fewer[i][j] = Square::Blank;
+                assert!(!unique(&fewer));
+            }
+        }
+
+        let full = clues_of(&random_region(6, 5, &mut Rng::new(7)));
+        let minimal = minimize(&full, true, 1_000_000).unwrap();
+        assert!(unique(&minimal));
+        for i in 0..5 {
+            for j in 0..6 {
+                assert_eq!(
+                    minimal[i][j] == Square::Blank,
+                    minimal[4 - i][5 - j] == Square::Blank
+                );
+            }
+        }
+
+        assert_eq!(
+            minimize(&vec![vec![Square::Blank; 2]; 2], false, 1_000),
+            None
+        );
+    }
+}
@@ -1,4 +1,6 @@
+pub mod bitgrid;
 pub mod board;
 pub mod examples;
+pub mod generate;
 pub mod square;
 pub mod uf;
@@ -1,25 +1,625 @@
 use std::io::stdin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use slither_link_solver::{
-    board::{Board, Stat},
-    square::parse_from_puzz_link,
+    board::{Board, GiveUp, SearchResult, SolutionCount, Technique},
+    generate::{generate, minimize},
+    square::{parse_from_puzz_link, to_puzz_link, Square},
 };
 
+const USAGE: &str = "\
+usage: slither-link-solver [COMMAND] [OPTIONS] [URL...]
+
+Puzzles are read from the URL arguments, or one per line from stdin if there are none.
+
+commands:
+  solve      solve each puzzle (the default)
+  check      tell whether each puzzle has exactly one solution
+  count      count the solutions of each puzzle
+  grade      rate the techniques each puzzle needs
+  minimize   remove redundant clues from each puzzle
+  render     print each puzzle without solving it
+  cnf        print each puzzle as DIMACS CNF, without the single-loop constraint
+  generate   generate a puzzle (no URL arguments)
+  batch FILE solve every puzzle in FILE and print a summary
+
+options:
+  --limit N           search with at most N calls (default 10000)
+  --time-limit SECS   give up searching each puzzle after SECS seconds
+  --format FORMAT     ascii, json or url (default ascii)
+  --quiet             leave out call counts, statistics and per-puzzle batch lines
+  --stats             solve: print search statistics
+  --cdcl              solve, batch: search with clause learning
+  --threads N         solve: search with N threads, or one per core if N is 0 (default 1)
+  --jobs N            batch: solve N puzzles at a time, or one per core if N is 0 (default 1)
+  --max N             count: stop after N solutions
+  --symmetric         minimize: keep 180-degree symmetry
+  --width N           generate: the width (default 10)
+  --height N          generate: the height (default 10)
+  --seed N            generate: the random seed (default 0)
+  --difficulty LEVEL  generate: the hardest technique needed, counting, patterns, loops
+                      or trial (default trial)
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Solve,
+    Check,
+    Count,
+    Grade,
+    Minimize,
+    Render,
+    Cnf,
+    Generate,
+    Batch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    Json,
+    Url,
+}
+
+struct Options {
+    command: Command,
+    inputs: Vec<String>,
+    limit: u64,
+    time_limit: Option<Duration>,
+    format: Format,
+    quiet: bool,
+    stats: bool,
+    cdcl: bool,
+    threads: usize,
+    jobs: usize,
+    max: u64,
+    symmetric: bool,
+    width: usize,
+    height: usize,
+    seed: u64,
+    difficulty: Technique,
+}
+
 fn main() {
-    for s in stdin().lines() {
-        let s = s.unwrap();
-        let board = if let Some(board) = parse_from_puzz_link(&s) {
-            board
-        } else {
-            eprintln!("error while parsing: {}", s);
-            continue;
-        };
-        let mut board = Board::new(&board);
-        let mut stat = Stat::default();
-        let limit = 10_000;
-        let result = board.search(&mut stat, limit);
-        println!("result = {}", result);
-        println!("{}", board);
-        println!("stat = {:?}", stat);
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+    };
+    match options.command {
+        Command::Generate => run_generate(&options),
+        Command::Batch => batch(&options),
+        _ => {
+            let lines: Vec<String> = if options.inputs.is_empty() {
+                stdin().lines().map(|s| s.unwrap()).collect()
+            } else {
+                options.inputs.clone()
+            };
+            for s in lines {
+                let s = s.trim();
+                if s.is_empty() {
+                    continue;
+                }
+                match parse_from_puzz_link(s) {
+                    Ok(init) => run(&options, s, &init),
+                    Err(e) => eprintln!("error while parsing: {}: {}", s, e),
+                }
+            }
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        command: Command::Solve,
+        inputs: vec![],
+        limit: 10_000,
+        time_limit: None,
+        format: Format::Ascii,
+        quiet: false,
+        stats: false,
+        cdcl: false,
+        threads: 1,
+        jobs: 1,
+        max: u64::MAX,
+        symmetric: false,
+        width: 10,
+        height: 10,
+        seed: 0,
+        difficulty: Technique::Trial,
+    };
+    let mut first = true;
+    while let Some(arg) = args.next() {
+        let is_first = std::mem::replace(&mut first, false);
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                std::process::exit(0);
+            }
+            "--limit" => options.limit = parse_number(&value()?)?,
+            "--time-limit" => {
+                let value = value()?;
+                let secs: f64 = parse_number(&value)?;
+                let time_limit = Duration::try_from_secs_f64(secs)
+                    .map_err(|_| format!("not a duration: {}", value))?;
+                options.time_limit = Some(time_limit);
+            }
+            "--threads" => options.threads = parse_number(&value()?)?,
+            "--jobs" => options.jobs = parse_number(&value()?)?,
+            "--max" => options.max = parse_number(&value()?)?,
+            "--width" => options.width = parse_number(&value()?)?,
+            "--height" => options.height = parse_number(&value()?)?,
+            "--seed" => options.seed = parse_number(&value()?)?,
+            "--format" => {
+                options.format = match value()?.as_str() {
+                    "ascii" => Format::Ascii,
+                    "json" => Format::Json,
+                    "url" => Format::Url,
+                    other => return Err(format!("unknown format: {}", other)),
+                }
+            }
+            "--difficulty" => {
+                options.difficulty = match value()?.as_str() {
+                    "counting" => Technique::Counting,
+                    "patterns" => Technique::Patterns,
+                    "loops" => Technique::Loops,
+                    "trial" => Technique::Trial,
+                    other => return Err(format!("unknown difficulty: {}", other)),
+                }
+            }
+            "--quiet" => options.quiet = true,
+            "--stats" => options.stats = true,
+            "--cdcl" => options.cdcl = true,
+            "--symmetric" => options.symmetric = true,
+            flag if flag.starts_with("--") => return Err(format!("unknown option: {}", flag)),
+            _ if is_first => {
+                options.command = match arg.as_str() {
+                    "solve" => Command::Solve,
+                    "check" => Command::Check,
+                    "count" => Command::Count,
+                    "grade" => Command::Grade,
+                    "minimize" => Command::Minimize,
+                    "render" => Command::Render,
+                    "cnf" => Command::Cnf,
+                    "generate" => Command::Generate,
+                    "batch" => Command::Batch,
+                    _ => {
+                        options.inputs.push(arg);
+                        Command::Solve
+                    }
+                }
+            }
+            _ => options.inputs.push(arg),
+        }
+    }
+    match options.command {
+        Command::Generate if !options.inputs.is_empty() => {
+            Err("generate takes no URL arguments".to_string())
+        }
+        Command::Generate if options.width == 0 || options.height == 0 => {
+            Err("the width and the height must be positive".to_string())
+        }
+        Command::Batch if options.inputs.len() != 1 => Err("batch takes one FILE".to_string()),
+        _ => Ok(options),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("not a number: {}", s))
+}
+
+// Quotes a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// A field of a result.
+enum Value {
+    Text(String),
+    Number(u64),
+    Bool(bool),
+}
+
+impl Value {
+    fn to_json(&self) -> String {
+        match self {
+            Value::Text(text) => json_string(text),
+            Value::Number(number) => number.to_string(),
+            Value::Bool(value) => value.to_string(),
+        }
+    }
+}
+
+impl core::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Text(text) => write!(f, "{}", text),
+            Value::Number(number) => write!(f, "{}", number),
+            Value::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+// Fields that --quiet leaves out.
+const STAT_FIELDS: [&str; 7] = [
+    "calls",
+    "branches",
+    "backtracks",
+    "max_depth",
+    "propagations",
+    "propagate_us",
+    "check_us",
+];
+
+// Prints a result with some fields and a board, in the chosen format.
+// The url format prints the puzzle URL after the fields, since solutions have no URL.
+fn print_result(options: &Options, fields: &[(&str, Value)], board: Option<&Board>) {
+    let url = board.map(|board| to_puzz_link(board.clues()));
+    let fields = fields
+        .iter()
+        .filter(|(key, _)| !(options.quiet && STAT_FIELDS.contains(key)));
+    match options.format {
+        Format::Json => {
+            let mut items: Vec<_> = fields
+                .map(|(key, value)| format!("{}:{}", json_string(key), value.to_json()))
+                .collect();
+            if let (Some(board), Some(url)) = (board, &url) {
+                items.push(format!("\"url\":{}", json_string(url)));
+                let rows: Vec<_> = board
+                    .to_string()
+                    .lines()
+                    .map(json_string)
+                    .collect::<Vec<_>>();
+                items.push(format!("\"board\":[{}]", rows.join(",")));
+            }
+            println!("{{{}}}", items.join(","));
+        }
+        Format::Ascii | Format::Url => {
+            let values: Vec<_> = fields
+                .map(|(key, value)| format!("{} = {}", key, value))
+                .collect();
+            if !values.is_empty() {
+                println!("{}", values.join(", "));
+            }
+            match (board, options.format) {
+                (Some(_), Format::Url) => println!("{}", url.unwrap()),
+                (Some(board), _) => println!("{}", board),
+                _ => {}
+            }
+        }
+    }
+}
+
+// A board whose searches stop at the time limit, counted from now.
+fn new_board<'sq>(options: &Options, init: &'sq [Vec<Square>]) -> Board<'sq> {
+    let mut board = Board::new(init);
+    board.set_deadline(
+        options
+            .time_limit
+            .map(|time_limit| Instant::now() + time_limit),
+    );
+    board
+}
+
+fn run(options: &Options, s: &str, init: &[Vec<Square>]) {
+    let limit = options.limit;
+    match options.command {
+        Command::Solve => {
+            let board = new_board(options, init);
+            let result = if options.cdcl {
+                board.search_cdcl(limit)
+            } else if options.threads == 1 {
+                board.search(limit)
+            } else {
+                board.search_parallel(limit, options.threads)
+            };
+            let stat = result.stat().clone();
+            let (text, solution) = match result {
+                SearchResult::Solved(solution, _) => ("solved".to_string(), Some(solution)),
+                SearchResult::NoSolution(_) => ("no solution".to_string(), None),
+                SearchResult::MultipleSolutions(..) => unreachable!(),
+                SearchResult::GaveUp(reason, _) => (format!("gave up: {}", reason), None),
+            };
+            let mut fields = vec![
+                ("result", Value::Text(text)),
+                ("calls", Value::Number(stat.num_call())),
+            ];
+            if options.stats && options.format == Format::Json {
+                let micros = |time: Duration| Value::Number(time.as_micros() as u64);
+                fields.extend([
+                    ("branches", Value::Number(stat.num_branch())),
+                    ("backtracks", Value::Number(stat.num_backtrack())),
+                    ("max_depth", Value::Number(stat.max_depth())),
+                    ("propagations", Value::Number(stat.num_propagate())),
+                    ("propagate_us", micros(stat.propagate_time())),
+                    ("check_us", micros(stat.check_time())),
+                ]);
+            }
+            print_result(
+                options,
+                &fields,
+                Some(solution.as_deref().unwrap_or(&board)),
+            );
+            if options.stats && !options.quiet && options.format != Format::Json {
+                println!("{}", stat);
+            }
+        }
+        Command::Check => {
+            let result = new_board(options, init).search_unique(limit);
+            let verdict = match result {
+                SearchResult::NoSolution(_) => "none",
+                SearchResult::Solved(..) => "unique",
+                SearchResult::MultipleSolutions(..) => "multiple",
+                SearchResult::GaveUp(..) => "unknown",
+            };
+            let fields = [
+                ("puzzle", Value::Text(s.to_string())),
+                ("solutions", Value::Text(verdict.to_string())),
+                ("calls", Value::Number(result.stat().num_call())),
+            ];
+            print_result(options, &fields, None);
+        }
+        Command::Count => {
+            let (count, stat) = new_board(options, init).count_solutions(limit, options.max);
+            let (count, exact) = match count {
+                SolutionCount::Exactly(count) => (count, true),
+                SolutionCount::AtLeast(count) => (count, false),
+            };
+            let fields = [
+                ("puzzle", Value::Text(s.to_string())),
+                ("solutions", Value::Number(count)),
+                ("exact", Value::Bool(exact)),
+                ("calls", Value::Number(stat.num_call())),
+            ];
+            print_result(options, &fields, None);
+        }
+        Command::Grade => {
+            let mut board = Board::new(init);
+            let grade = board.grade();
+            let hardest = match (grade.solved, grade.hardest()) {
+                (false, _) => "unsolved".to_string(),
+                (true, None) => "trivial".to_string(),
+                (true, Some(hardest)) => format!("{:?}", hardest).to_lowercase(),
+            };
+            let mut fields = vec![
+                ("puzzle", Value::Text(s.to_string())),
+                ("grade", Value::Text(hardest)),
+            ];
+            let names: Vec<_> = Technique::ALL
+                .iter()
+                .map(|technique| format!("{:?}", technique).to_lowercase())
+                .collect();
+            for (technique, name) in Technique::ALL.iter().zip(&names) {
+                fields.push((name, Value::Number(grade.uses(*technique))));
+            }
+            print_result(options, &fields, None);
+        }
+        Command::Minimize => match minimize(init, options.symmetric, limit) {
+            Some(puzzle) => {
+                let clues = puzzle
+                    .iter()
+                    .flatten()
+                    .filter(|&&square| square != Square::Blank)
+                    .count();
+                print_result(
+                    options,
+                    &[("clues", Value::Number(clues as u64))],
+                    Some(&Board::new(&puzzle)),
+                );
+            }
+            None => print_result(
+                options,
+                &[
+                    ("puzzle", Value::Text(s.to_string())),
+                    ("error", Value::Text("not known to be unique".to_string())),
+                ],
+                None,
+            ),
+        },
+        Command::Render => print_result(options, &[], Some(&Board::new(init))),
+        Command::Cnf => print!("{}", Board::new(init).to_cnf().to_dimacs()),
+        Command::Generate | Command::Batch => unreachable!(),
+    }
+}
+
+fn run_generate(options: &Options) {
+    let grade = format!("{:?}", options.difficulty).to_lowercase();
+    match generate(
+        options.width,
+        options.height,
+        options.seed,
+        options.difficulty,
+    ) {
+        Some(puzzle) => print_result(
+            options,
+            &[("grade", Value::Text(grade))],
+            Some(&Board::new(&puzzle)),
+        ),
+        None => print_result(
+            options,
+            &[(
+                "error",
+                Value::Text(format!("no {} puzzle of this size found", grade)),
+            )],
+            None,
+        ),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Solved,
+    Unsolved,
+    GaveUp,
+    Timeout,
+    ParseError,
+}
+
+impl Status {
+    const ALL: [Status; 5] = [
+        Status::Solved,
+        Status::Unsolved,
+        Status::GaveUp,
+        Status::Timeout,
+        Status::ParseError,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Solved => "solved",
+            Status::Unsolved => "unsolved",
+            Status::GaveUp => "gave up",
+            Status::Timeout => "timeout",
+            Status::ParseError => "parse error",
+        }
+    }
+}
+
+struct Report {
+    line: usize,
+    status: Status,
+    num_call: u64,
+    time: Duration,
+}
+
+// Solves every non-empty line of the file and prints one line per puzzle, then a summary.
+fn batch(options: &Options) {
+    let path = &options.inputs[0];
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("error while reading {}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    let lines: Vec<_> = text
+        .lines()
+        .enumerate()
+        .map(|(k, s)| (k + 1, s.trim()))
+        .filter(|(_, s)| !s.is_empty())
+        .collect();
+    let jobs = if options.jobs == 0 {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    } else {
+        options.jobs
+    };
+    let next = AtomicUsize::new(0);
+    // The number of reports printed so far, and the reports by position in the file.
+    let done = Mutex::new((0, lines.iter().map(|_| None).collect::<Vec<_>>()));
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.min(lines.len()) {
+            scope.spawn(|| {
+                while let Some(&(line, s)) = lines.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let report = solve_one(line, s, options);
+                    let mut done = done.lock().unwrap();
+                    let (printed, reports) = &mut *done;
+                    let k = lines.partition_point(|&(other, _)| other < line);
+                    reports[k] = Some(report);
+                    // Print in the order of the file, as soon as the earlier puzzles are done.
+                    while let Some(Some(report)) = reports.get(*printed) {
+                        if !options.quiet {
+                            println!(
+                                "{}\t{}\t{}\t{:.3}ms",
+                                report.line,
+                                report.status.as_str(),
+                                report.num_call,
+                                report.time.as_secs_f64() * 1e3,
+                            );
+                        }
+                        *printed += 1;
+                    }
+                }
+            });
+        }
+    });
+    let (_, reports) = done.into_inner().unwrap();
+    let reports: Vec<_> = reports.into_iter().map(Option::unwrap).collect();
+    summarize(&reports);
+}
+
+fn solve_one(line: usize, s: &str, options: &Options) -> Report {
+    let start = Instant::now();
+    let init = match parse_from_puzz_link(s) {
+        Ok(init) => init,
+        Err(_) => {
+            return Report {
+                line,
+                status: Status::ParseError,
+                num_call: 0,
+                time: start.elapsed(),
+            }
+        }
+    };
+    let board = new_board(options, &init);
+    let result = if options.cdcl {
+        board.search_cdcl(options.limit)
+    } else {
+        board.search(options.limit)
+    };
+    let status = match result {
+        SearchResult::Solved(..) | SearchResult::MultipleSolutions(..) => Status::Solved,
+        SearchResult::NoSolution(_) => Status::Unsolved,
+        SearchResult::GaveUp(GiveUp::Timeout, _) => Status::Timeout,
+        SearchResult::GaveUp(..) => Status::GaveUp,
+    };
+    Report {
+        line,
+        status,
+        num_call: result.stat().num_call(),
+        time: start.elapsed(),
+    }
+}
+fn summarize(reports: &[Report]) {
+    println!("---");
+    for status in Status::ALL {
+        let count = reports.iter().filter(|r| r.status == status).count();
+        println!("{}: {}", status.as_str(), count);
+    }
+    let total_calls: u64 = reports.iter().map(|r| r.num_call).sum();
+    let total_time: Duration = reports.iter().map(|r| r.time).sum();
+    println!("total calls: {}", total_calls);
+    println!("total time: {:.3}s", total_time.as_secs_f64());
+    let mut by_time: Vec<_> = reports.iter().collect();
+    by_time.sort_by_key(|r| std::cmp::Reverse(r.time));
+    if by_time.is_empty() {
+        return;
+    }
+    let mut calls: Vec<_> = reports.iter().map(|r| r.num_call).collect();
+    calls.sort_unstable();
+    // Nearest-rank percentiles.
+    for p in [50, 90, 99, 100] {
+        let rank = (reports.len() * p).div_ceil(100).max(1) - 1;
+        println!(
+            "p{}: {:.3}ms, {} calls",
+            p,
+            by_time[reports.len() - 1 - rank].time.as_secs_f64() * 1e3,
+            calls[rank],
+        );
+    }
+    println!("slowest:");
+    for r in by_time.iter().take(5) {
+        println!(
+            "  line {}: {}, {} calls, {:.3}ms",
+            r.line,
+            r.status.as_str(),
+            r.num_call,
+            r.time.as_secs_f64() * 1e3,
+        );
     }
 }
@@ -1,4 +1,4 @@
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Square {
     Blank,
     Zero,
@@ -43,28 +43,84 @@ impl Square {
     }
 }
 
+/// The reason why a puzz.link URL was rejected.
+/// Offsets are byte offsets into the whole input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The input doesn't start with `https://puzz.link/p?slither/` or `https://puzz.link/p?slither_edit/`.
+    InvalidPrefix,
+    /// The width or height field starting at `offset` isn't a positive integer followed by '/'.
+    InvalidSize { offset: usize },
+    /// The board has `width * height` cells, which is not less than 10,000.
+    TooLarge { width: usize, height: usize },
+    /// `character` at `offset` can't appear in the data part.
+    InvalidCharacter { offset: usize, character: char },
+    /// The data part ends after describing only `actual` of `expected` cells.
+    TooLittleData { expected: usize, actual: usize },
+    /// All cells are already described before `offset`, but the data continues.
+    TooMuchData { offset: usize },
+}
+
+impl core::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            ParseError::InvalidPrefix => f.write_str("not a puzz.link slither URL"),
+            ParseError::InvalidSize { offset } => write!(f, "invalid size at offset {}", offset),
+            ParseError::TooLarge { width, height } => {
+                write!(f, "board of size {}x{} is too large", width, height)
+            }
+            ParseError::InvalidCharacter { offset, character } => {
+                write!(f, "invalid character {:?} at offset {}", character, offset)
+            }
+            ParseError::TooLittleData { expected, actual } => {
+                write!(f, "data describes only {} of {} cells", actual, expected)
+            }
+            ParseError::TooMuchData { offset } => {
+                write!(f, "unexpected data after all cells at offset {}", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 /// Parses a URL like https://puzz.link/p?slither_link/9/9/.2zzzy
 /// or https://puzz.link/p?slither_link_edit/9/9/.2zzzy.
-pub fn parse_from_puzz_link(s: &str) -> Option<Vec<Vec<Square>>> {
-    let s = s.strip_prefix("https://puzz.link/p?slither")?;
-    let s = if let Some(t) = s.strip_prefix('/') {
-        t
-    } else if let Some(t) = s.strip_prefix("_edit/") {
-        t
-    } else {
-        return None;
-    };
-    let split: Vec<_> = s.split('/').collect();
-    if split.len() != 3 {
-        return None;
+pub fn parse_from_puzz_link(s: &str) -> Result<Vec<Vec<Square>>, ParseError> {
+    let rest = s
+        .strip_prefix("https://puzz.link/p?slither")
+        .ok_or(ParseError::InvalidPrefix)?;
+    let rest = rest
+        .strip_prefix('/')
+        .or_else(|| rest.strip_prefix("_edit/"))
+        .ok_or(ParseError::InvalidPrefix)?;
+    let mut offset = s.len() - rest.len();
+    let mut size = [0; 2];
+    for value in &mut size {
+        let field = s[offset..]
+            .split_once('/')
+            .map(|(field, _)| field)
+            .ok_or(ParseError::InvalidSize { offset })?;
+        *value = match field.parse::<usize>() {
+            Ok(value) if value > 0 => value,
+            _ => return Err(ParseError::InvalidSize { offset }),
+        };
+        offset += field.len() + 1;
     }
-    let m = split[0].parse::<usize>().ok()?;
-    let n = split[1].parse::<usize>().ok()?;
-    if n * m >= 10_000 || split[2].len() >= 10_000 {
-        return None;
+    let [m, n] = size;
+    if n.checked_mul(m).is_none_or(|size| size >= 10_000) {
+        return Err(ParseError::TooLarge {
+            width: m,
+            height: n,
+        });
     }
     let mut data = vec![];
-    for c in split[2].chars() {
+    for (pos, c) in s[offset..].char_indices() {
+        if data.len() >= n * m {
+            return Err(ParseError::TooMuchData {
+                offset: offset + pos,
+            });
+        }
         if c == '.' {
             // '?' is treated as an empty square.
             data.push(Square::Blank);
@@ -81,21 +137,153 @@ pub fn parse_from_puzz_link(s: &str) -> Option<Vec<Vec<Square>>> {
             } else {
                 c as u8 - b'a' + 10
             };
-            data.push(Square::new_number(dig % 5)?);
+            // dig % 5 is at most 4, so this never fails.
+            data.push(Square::new_number(dig % 5).unwrap());
             for _ in 0..dig / 5 {
                 data.push(Square::Blank);
             }
             continue;
         }
-        return None;
+        return Err(ParseError::InvalidCharacter {
+            offset: offset + pos,
+            character: c,
+        });
     }
-    data.truncate(n * m);
-    if data.len() != n * m {
-        return None;
+    if data.len() < n * m {
+        return Err(ParseError::TooLittleData {
+            expected: n * m,
+            actual: data.len(),
+        });
     }
+    data.truncate(n * m);
     let mut ret = vec![vec![Square::Blank; m]; n];
     for i in 0..n {
         ret[i].copy_from_slice(&data[i * m..i * m + m]);
     }
-    Some(ret)
+    Ok(ret)
+}
+
+/// Encodes a board as a URL like https://puzz.link/p?slither/9/9/.2zzzy.
+/// This is the inverse of [`parse_from_puzz_link`].
+pub fn to_puzz_link(board: &[Vec<Square>]) -> String {
+    let n = board.len();
+    let m = board.first().map_or(0, |row| row.len());
+    let cells: Vec<Square> = board.iter().flatten().copied().collect();
+    let mut data = String::new();
+    let mut blanks = 0;
+    let mut pos = 0;
+    while pos < cells.len() {
+        let number = if let Some(number) = cells[pos].get_number() {
+            number
+        } else {
+            blanks += 1;
+            pos += 1;
+            if blanks == 20 {
+                data.push('z');
+                blanks = 0;
+            }
+            continue;
+        };
+        if blanks > 0 {
+            data.push((b'f' + blanks) as char);
+            blanks = 0;
+        }
+        // Up to two following blanks are folded into the digit.
+        // Cells past the end count as blanks, as puzz.link does.
+        let mut dig = number;
+        pos += 1;
+        for _ in 0..2 {
+            if cells.get(pos).is_some_and(|&sq| sq != Square::Blank) {
+                break;
+            }
+            dig += 5;
+            pos += 1;
+        }
+        data.push(char::from_digit(u32::from(dig), 16).unwrap());
+    }
+    if blanks > 0 {
+        data.push((b'f' + blanks) as char);
+    }
+    format!("https://puzz.link/p?slither/{}/{}/{}", m, n, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        // The lines of puzzles.txt that aren't slither URLs this parser accepts.
+        let rejected = [
+            (203, ParseError::InvalidPrefix),
+            (269, ParseError::InvalidPrefix),
+            (270, ParseError::InvalidPrefix),
+            (549, ParseError::TooMuchData { offset: 113 }),
+        ];
+        for (k, url) in include_str!("../puzzles.txt").lines().enumerate() {
+            let board = match parse_from_puzz_link(url) {
+                Ok(board) => board,
+                Err(error) => {
+                    assert!(
+                        rejected.contains(&(k + 1, error.clone())),
+                        "{}: {}",
+                        url,
+                        error
+                    );
+                    continue;
+                }
+            };
+            assert!(rejected.iter().all(|&(line, _)| line != k + 1), "{}", url);
+            let encoded = to_puzz_link(&board);
+            assert_eq!(
+                parse_from_puzz_link(&encoded).as_ref(),
+                Ok(&board),
+                "{}",
+                url
+            );
+        }
+        let url = "https://puzz.link/p?slither/10/10/ld1080ddnbg2836dn1dn5380dgdnc0632daj";
+        assert_eq!(to_puzz_link(&parse_from_puzz_link(url).unwrap()), url);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse_from_puzz_link("https://puzz.link/p?slither_play/2/2/g"),
+            Err(ParseError::InvalidPrefix),
+        );
+        assert_eq!(
+            parse_from_puzz_link("https://puzz.link/p?slither/2/x/g"),
+            Err(ParseError::InvalidSize { offset: 30 }),
+        );
+        assert_eq!(
+            parse_from_puzz_link("https://puzz.link/p?slither/100/100/g"),
+            Err(ParseError::TooLarge {
+                width: 100,
+                height: 100,
+            }),
+        );
+        assert_eq!(
+            parse_from_puzz_link("https://puzz.link/p?slither/2/2/1f"),
+            Err(ParseError::InvalidCharacter {
+                offset: 33,
+                character: 'f',
+            }),
+        );
+        assert_eq!(
+            parse_from_puzz_link("https://puzz.link/p?slither/2/2/1g"),
+            Err(ParseError::TooLittleData {
+                expected: 4,
+                actual: 2,
+            }),
+        );
+        assert_eq!(
+            parse_from_puzz_link("https://puzz.link/p?slither/2/2/j0"),
+            Err(ParseError::TooMuchData { offset: 33 }),
+        );
+        assert_eq!(
+            parse_from_puzz_link("https://puzz.link/p?slither_edit/2/1/8"),
+            Ok(vec![vec![Square::Three, Square::Blank]]),
+        );
+    }
 }